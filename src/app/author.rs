@@ -0,0 +1,81 @@
+use crate::app::event::ChatMessage;
+use chrono::{DateTime, Utc};
+use std::collections::HashSet;
+
+/// Cells taken by the avatar in the author popup.
+pub const LARGE_AVATAR_COLS: u16 = 8;
+pub const LARGE_AVATAR_ROWS: u16 = 4;
+/// Messages kept in the popup, the newest win.
+pub const MAX_AUTHOR_MESSAGES: usize = 200;
+
+/// When an author first spoke in this session and how often since, kept past trimming.
+#[derive(Debug, Clone, Copy)]
+pub struct AuthorSeen {
+    pub first_seen: DateTime<Utc>,
+    pub count: usize,
+}
+
+/// Past messages of an author found in the archive, see `Archive::author_history`.
+#[derive(Debug, Clone)]
+pub struct ArchivedAuthor {
+    pub count: usize,
+    pub first_seen: Option<DateTime<Utc>>,
+    pub messages: Vec<ChatMessage>,
+}
+
+/// Everything known about one author, opened with Enter on a selected message.
+pub struct AuthorDetails {
+    /// The author's newest message, carries the name, roles and avatar.
+    pub author: ChatMessage,
+    pub first_seen: Option<DateTime<Utc>>,
+    pub session_count: usize,
+    /// `None` unless archiving is enabled.
+    pub archived_count: Option<usize>,
+    /// The author's messages from the view and the archive, oldest first.
+    pub messages: Vec<ChatMessage>,
+    /// Rows scrolled back from the newest message.
+    pub scroll_offset: usize,
+}
+
+impl AuthorDetails {
+    pub fn new(
+        author: ChatMessage,
+        seen: Option<AuthorSeen>,
+        session: Vec<ChatMessage>,
+        archived: Option<ArchivedAuthor>,
+    ) -> Self {
+        let ids: HashSet<&str> = session
+            .iter()
+            .map(|m| m.id.as_str())
+            .filter(|id| !id.is_empty())
+            .collect();
+        let mut messages: Vec<ChatMessage> = archived
+            .iter()
+            .flat_map(|a| &a.messages)
+            .filter(|m| !ids.contains(m.id.as_str()))
+            .cloned()
+            .chain(session.iter().cloned())
+            .collect();
+        messages.sort_by_key(|m| m.timestamp);
+        let excess = messages.len().saturating_sub(MAX_AUTHOR_MESSAGES);
+        messages.drain(..excess);
+
+        let first_seen = [
+            seen.map(|s| s.first_seen),
+            archived.as_ref().and_then(|a| a.first_seen),
+            messages.first().map(|m| m.timestamp),
+        ]
+        .into_iter()
+        .flatten()
+        .min();
+
+        Self {
+            author,
+            first_seen,
+            session_count: seen.map_or(session.len(), |s| s.count),
+            archived_count: archived.map(|a| a.count),
+            messages,
+            scroll_offset: 0,
+        }
+    }
+}
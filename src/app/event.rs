@@ -1,51 +1,296 @@
 use std::sync::Arc;
 
+use crate::app::View;
+use crate::app::author::ArchivedAuthor;
+
+use chrono::{DateTime, Utc};
 use ratatui::crossterm::event::KeyEvent;
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::SendError;
+
+/// Index of a watched stream, in the order the targets were passed on the command line.
+pub type StreamId = usize;
 
 #[derive(Debug, Clone)]
 pub enum AppEvent {
     Tick,
     Input(KeyEvent),
-    Chat(ChatMessage),
+    Stream(StreamId, StreamEvent),
+    /// A downloaded avatar for an author whose messages went out without one.
+    AvatarReady {
+        channel_id: String,
+        avatar: Arc<Avatar>,
+    },
+    /// Archived messages older than `before_id`, the oldest message `view` had when asked.
+    History {
+        view: View,
+        before_id: String,
+        messages: Result<Vec<ChatMessage>, AppError>,
+    },
+    /// What the archive has on an author whose popup was asked for in `view`.
+    AuthorHistory {
+        view: View,
+        channel_id: String,
+        archived: Result<ArchivedAuthor, AppError>,
+    },
+}
+
+/// Events produced by the tasks of a single stream.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum StreamEvent {
+    Chat(Box<ChatMessage>),
+    Moderation(ModerationEvent),
+    Poll(Poll),
     Status(StatusEvent),
-    Error(String),
+    Error(AppError),
     StatsUpdate(StatsMessage),
-    Quit,
+    Outgoing(OutgoingResult),
+}
+
+/// Sender handed to per-stream tasks, tags everything it sends with the stream id.
+#[derive(Debug, Clone)]
+pub struct StreamSender {
+    stream: StreamId,
+    tx: mpsc::Sender<AppEvent>,
+}
+
+impl StreamSender {
+    pub fn new(stream: StreamId, tx: mpsc::Sender<AppEvent>) -> Self {
+        Self { stream, tx }
+    }
+
+    pub async fn send(&self, event: StreamEvent) -> Result<(), SendError<AppEvent>> {
+        self.tx.send(AppEvent::Stream(self.stream, event)).await
+    }
+
+    pub fn stream(&self) -> StreamId {
+        self.stream
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AppError {
+    pub category: ErrorCategory,
+    pub message: String,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCategory {
+    QuotaExceeded,
+    AuthExpired,
+    ChatNotFound,
+    Network,
+    Other,
+}
+
+/// Requests from the UI that the YouTube command task carries out.
 #[derive(Debug, Clone)]
+pub enum Command {
+    SendMessage {
+        local_id: String,
+        text: String,
+    },
+    DeleteMessage {
+        message_id: String,
+    },
+    /// Permanent ban when `duration_secs` is `None`, timeout otherwise.
+    BanUser {
+        channel_id: String,
+        duration_secs: Option<u64>,
+    },
+}
+
+/// Outcome of a `Command::SendMessage`, matched to the local echo by `local_id`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OutgoingResult {
+    Sent {
+        local_id: String,
+        message_id: String,
+    },
+    Failed {
+        local_id: String,
+        error: AppError,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct StatsMessage {
     pub viewer_count: u32,
 }
 
+/// An author's profile picture, its RGBA pixels are in the file at `path`.
 #[derive(Debug, Clone)]
-pub struct KittyAvatar {
+pub struct Avatar {
     pub id: u32,
+    /// Width of the slot in front of chat messages, in cells.
     pub cols: u16,
     pub width: u32,
     pub height: u32,
     pub path: String,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct ChatMessage {
-    pub timestamp: String,
+    pub id: String,
+    pub stream: StreamId,
+    pub timestamp: DateTime<Utc>,
     pub author: String,
+    pub author_channel_id: String,
     pub message: String,
     pub kind: MessageKind,
-    pub avatar: Option<Arc<KittyAvatar>>,
-    pub is_member: bool
+    #[serde(skip)]
+    pub avatar: Option<Arc<Avatar>>,
+    pub is_member: bool,
+    pub is_moderator: bool,
+    pub is_owner: bool,
+    pub is_verified: bool,
+    /// Membership level name, known once the author had a membership event in this session.
+    pub member_level: Option<String>,
+    pub removed: Option<Removal>,
+    /// Set on our own messages until YouTube confirms them.
+    pub delivery: Option<Delivery>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Delivery {
+    Pending,
+    Failed,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Why a message is no longer visible on YouTube. Removed messages stay in the
+/// history, rendered struck through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Removal {
+    Deleted,
+    Retracted,
+    AuthorBanned,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum MessageKind {
     Text,
-    Subscription,
+    /// New member, or an existing member moving to a higher level.
+    Subscription {
+        level: String,
+        is_upgrade: bool,
+    },
+    /// Member sharing how long they've been a member, the message holds their comment.
+    MemberMilestone {
+        level: String,
+        months: u32,
+    },
+    MembershipGifting {
+        count: i32,
+        level: String,
+    },
+    /// Recipient side of a gifting event. `gifter` is resolved from the gifting
+    /// message once it's in the history.
+    GiftMembershipReceived {
+        level: String,
+        gifting_message_id: String,
+        gifter: Option<String>,
+    },
+    /// Paid message, `amount` is the display string (e.g. "$5.00") and `tier`
+    /// follows YouTube's color tiers.
+    SuperChat {
+        amount: String,
+        tier: u32,
+    },
+    /// Paid sticker, the message holds the sticker's alt text.
+    SuperSticker {
+        amount: String,
+        tier: u32,
+    },
+    /// Line generated by the app itself, e.g. ban notices.
+    System,
 }
 
-#[derive(Debug, Clone)]
+impl MessageKind {
+    /// Membership level the author holds according to this message, if it says.
+    pub fn member_level(&self) -> Option<&str> {
+        match self {
+            MessageKind::Subscription { level, .. }
+            | MessageKind::MemberMilestone { level, .. }
+            | MessageKind::GiftMembershipReceived { level, .. } => {
+                Some(level.as_str()).filter(|l| !l.is_empty())
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ModerationEvent {
+    MessageDeleted {
+        message_id: String,
+    },
+    MessageRetracted {
+        message_id: String,
+    },
+    /// `duration_secs` is `None` for permanent bans.
+    UserBanned {
+        channel_id: String,
+        display_name: String,
+        duration_secs: Option<u64>,
+        timestamp: DateTime<Utc>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Poll {
+    pub id: String,
+    pub question: String,
+    pub options: Vec<PollOption>,
+    pub status: PollStatus,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PollOption {
+    pub text: String,
+    pub tally: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PollStatus {
+    Active,
+    Closed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum StatusEvent {
     Connecting,
     Connected,
-    Disconnected,
+    Disconnected(DisconnectReason),
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisconnectReason {
+    /// The broadcast went offline, `at` is when it ended if YouTube reports it.
+    StreamEnded { at: Option<DateTime<Utc>> },
+    /// Connection dropped, the chat task is retrying.
+    Error(String),
+    /// YouTube no longer knows the live chat, the chat task has given up.
+    ChatGone,
+}
+
+impl DisconnectReason {
+    /// Whether the chat task stopped for good, every exit of a chat task ends with one.
+    pub fn is_final(&self) -> bool {
+        !matches!(self, DisconnectReason::Error(_))
+    }
 }
@@ -0,0 +1,200 @@
+use crate::app::event::{ChatMessage, MessageKind};
+
+/// Which messages a view shows, `f` cycles through them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Filter {
+    All,
+    Members,
+    /// Moderators and the channel owner.
+    Moderators,
+    Verified,
+    /// Super Chats, Super Stickers and memberships.
+    Paid,
+    /// Everything from one channel, picked with `a` in selection mode.
+    Author {
+        channel_id: String,
+        name: String,
+    },
+}
+
+impl Filter {
+    pub fn matches(&self, m: &ChatMessage) -> bool {
+        match self {
+            Filter::All => true,
+            Filter::Members => m.is_member,
+            Filter::Moderators => m.is_moderator || m.is_owner,
+            Filter::Verified => m.is_verified,
+            Filter::Paid => matches!(
+                m.kind,
+                MessageKind::SuperChat { .. }
+                    | MessageKind::SuperSticker { .. }
+                    | MessageKind::Subscription { .. }
+                    | MessageKind::MemberMilestone { .. }
+                    | MessageKind::MembershipGifting { .. }
+            ),
+            Filter::Author { channel_id, .. } => m.author_channel_id == *channel_id,
+        }
+    }
+
+    pub fn next(&self) -> Filter {
+        match self {
+            Filter::All => Filter::Members,
+            Filter::Members => Filter::Moderators,
+            Filter::Moderators => Filter::Verified,
+            Filter::Verified => Filter::Paid,
+            Filter::Paid | Filter::Author { .. } => Filter::All,
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            Filter::All => "all".to_string(),
+            Filter::Members => "members".to_string(),
+            Filter::Moderators => "moderators".to_string(),
+            Filter::Verified => "verified".to_string(),
+            Filter::Paid => "paid".to_string(),
+            Filter::Author { name, .. } => format!("author {}", name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::DateTime;
+
+    fn message(kind: MessageKind) -> ChatMessage {
+        ChatMessage {
+            id: "m1".to_string(),
+            stream: 0,
+            timestamp: DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+            author: "viewer".to_string(),
+            author_channel_id: "UC1".to_string(),
+            message: "hi".to_string(),
+            kind,
+            avatar: None,
+            is_member: false,
+            is_moderator: false,
+            is_owner: false,
+            is_verified: false,
+            member_level: None,
+            removed: None,
+            delivery: None,
+        }
+    }
+
+    #[test]
+    fn role_filters_match_their_badges() {
+        let plain = message(MessageKind::Text);
+        let member = ChatMessage {
+            is_member: true,
+            ..plain.clone()
+        };
+        let moderator = ChatMessage {
+            is_moderator: true,
+            ..plain.clone()
+        };
+        let owner = ChatMessage {
+            is_owner: true,
+            ..plain.clone()
+        };
+        let verified = ChatMessage {
+            is_verified: true,
+            ..plain.clone()
+        };
+        let all = [&plain, &member, &moderator, &owner, &verified];
+
+        let matching = |filter: Filter| all.map(|m| filter.matches(m));
+        assert_eq!(matching(Filter::All), [true; 5]);
+        assert_eq!(
+            matching(Filter::Members),
+            [false, true, false, false, false]
+        );
+        assert_eq!(
+            matching(Filter::Moderators),
+            [false, false, true, true, false]
+        );
+        assert_eq!(
+            matching(Filter::Verified),
+            [false, false, false, false, true]
+        );
+    }
+
+    #[test]
+    fn paid_filter_matches_money_and_memberships() {
+        let level = || "Gold".to_string();
+        let paid = [
+            MessageKind::SuperChat {
+                amount: "$5.00".to_string(),
+                tier: 2,
+            },
+            MessageKind::SuperSticker {
+                amount: "$2.00".to_string(),
+                tier: 1,
+            },
+            MessageKind::Subscription {
+                level: level(),
+                is_upgrade: false,
+            },
+            MessageKind::MemberMilestone {
+                level: level(),
+                months: 6,
+            },
+            MessageKind::MembershipGifting {
+                count: 5,
+                level: level(),
+            },
+        ];
+        for kind in paid {
+            assert!(Filter::Paid.matches(&message(kind.clone())), "{:?}", kind);
+        }
+
+        let member = ChatMessage {
+            is_member: true,
+            ..message(MessageKind::Text)
+        };
+        assert!(!Filter::Paid.matches(&member));
+        // the gift is counted once, on the gifter's side
+        let received = message(MessageKind::GiftMembershipReceived {
+            level: level(),
+            gifting_message_id: "m0".to_string(),
+            gifter: None,
+        });
+        assert!(!Filter::Paid.matches(&received));
+    }
+
+    #[test]
+    fn author_filter_matches_by_channel_not_name() {
+        let filter = Filter::Author {
+            channel_id: "UC1".to_string(),
+            name: "viewer".to_string(),
+        };
+        let renamed = ChatMessage {
+            author: "new name".to_string(),
+            ..message(MessageKind::Text)
+        };
+        let namesake = ChatMessage {
+            author_channel_id: "UC2".to_string(),
+            ..message(MessageKind::Text)
+        };
+        assert!(filter.matches(&renamed));
+        assert!(!filter.matches(&namesake));
+    }
+
+    #[test]
+    fn cycling_returns_to_all() {
+        let mut filter = Filter::All;
+        let mut labels = Vec::new();
+        for _ in 0..5 {
+            filter = filter.next();
+            labels.push(filter.label());
+        }
+        assert_eq!(labels, ["members", "moderators", "verified", "paid", "all"]);
+
+        let author = Filter::Author {
+            channel_id: "UC1".to_string(),
+            name: "viewer".to_string(),
+        };
+        assert_eq!(author.next(), Filter::All);
+    }
+}
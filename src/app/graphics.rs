@@ -0,0 +1,725 @@
+use crate::app::event::Avatar;
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+use clap::ValueEnum;
+use image::imageops::FilterType;
+use image::{ImageFormat, RgbaImage};
+use log::debug;
+use ratatui::buffer::Buffer;
+use ratatui::crossterm::terminal::window_size;
+use ratatui::layout::Rect;
+use ratatui::style::{Color, Modifier, Style};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+use std::io::{Cursor, Write, stdout};
+use std::sync::Arc;
+use std::time::Duration;
+
+const KITTY_PLACEHOLDER: char = '\u{10EEEE}';
+/// Combining marks kitty reads as row, column and id byte numbers on a placeholder, by value.
+const PLACEHOLDER_DIACRITICS: [char; 16] = [
+    '\u{0305}', '\u{030D}', '\u{030E}', '\u{0310}', '\u{0312}', '\u{033D}', '\u{033E}', '\u{033F}',
+    '\u{0346}', '\u{034A}', '\u{034B}', '\u{034C}', '\u{0350}', '\u{0351}', '\u{0352}', '\u{0357}',
+];
+/// How long the terminal gets to answer the capability queries.
+const QUERY_TIMEOUT: Duration = Duration::from_millis(500);
+/// Assumed when the terminal doesn't report its size in pixels.
+const DEFAULT_CELL_PIXELS: (u32, u32) = (10, 20);
+/// Base64 bytes per escape sequence when sending a kitty image inline.
+const KITTY_CHUNK: usize = 4096;
+/// Low bits of a kitty image id, the avatar id. The high byte picks the slot size.
+const KITTY_AVATAR_ID: u32 = 0x00FF_FFFF;
+
+/// How avatars are drawn, `--graphics`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum GraphicsMode {
+    /// Ask the terminal what it supports.
+    Auto,
+    /// Kitty graphics protocol with unicode placeholders.
+    Kitty,
+    /// DEC Sixel images.
+    Sixel,
+    /// iTerm2 inline images, also understood by WezTerm.
+    Iterm2,
+    /// Colored half-block characters, works in any truecolor terminal.
+    Halfblock,
+    /// No avatars.
+    None,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Kitty(KittyTransfer),
+    Sixel,
+    Iterm2,
+    HalfBlock,
+    None,
+}
+
+/// How kitty images reach the terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KittyTransfer {
+    /// The terminal reads the cached avatar file, only works when it runs on this machine.
+    File,
+    /// The pixels are sent inline, for terminals that can't see our files, e.g. over SSH.
+    Direct,
+}
+
+impl KittyTransfer {
+    /// Guesses from the environment, used when `--graphics kitty` skips the query.
+    fn guess() -> Self {
+        if std::env::var_os("SSH_CONNECTION").is_some() || std::env::var_os("SSH_TTY").is_some() {
+            KittyTransfer::Direct
+        } else {
+            KittyTransfer::File
+        }
+    }
+}
+
+impl GraphicsMode {
+    /// The backend to use, `Auto` queries the terminal, which must be in raw mode and
+    /// not read by anything else yet.
+    pub fn resolve(self) -> Backend {
+        let backend = match self {
+            GraphicsMode::Auto => detect(),
+            GraphicsMode::Kitty => Backend::Kitty(KittyTransfer::guess()),
+            GraphicsMode::Sixel => Backend::Sixel,
+            GraphicsMode::Iterm2 => Backend::Iterm2,
+            GraphicsMode::Halfblock => Backend::HalfBlock,
+            GraphicsMode::None => Backend::None,
+        };
+        debug!("graphics backend {:?}", backend);
+        backend
+    }
+}
+
+fn detect() -> Backend {
+    let Some(reply) = query_terminal() else {
+        return Backend::HalfBlock;
+    };
+    if reply.contains("\x1b_Gi=31;OK") {
+        // the second query has kitty read a file, which fails when it runs on another machine
+        return Backend::Kitty(if reply.contains("\x1b_Gi=32;OK") {
+            KittyTransfer::File
+        } else {
+            KittyTransfer::Direct
+        });
+    }
+    if reply.contains("\x1bP>|iTerm2") || reply.contains("\x1bP>|WezTerm") {
+        return Backend::Iterm2;
+    }
+    // attribute 4 in the primary device attributes is sixel support
+    if device_attributes(&reply).is_some_and(|attrs| attrs.contains(&4)) {
+        return Backend::Sixel;
+    }
+    Backend::HalfBlock
+}
+
+/// Sends kitty graphics queries for an inline image and a file, XTVERSION and primary
+/// device attributes, and collects the replies. Every terminal answers the last one, so
+/// it marks the end.
+#[cfg(unix)]
+fn query_terminal() -> Option<String> {
+    let probe = std::env::temp_dir().join(format!("ytc-probe-{}.rgb", std::process::id()));
+    let reply = std::fs::write(&probe, [0u8; 3])
+        .ok()
+        .and_then(|_| ask_terminal(&probe));
+    let _ = std::fs::remove_file(&probe);
+    reply
+}
+
+#[cfg(unix)]
+fn ask_terminal(probe: &std::path::Path) -> Option<String> {
+    use rustix::event::{PollFd, PollFlags, Timespec, poll};
+    use std::os::fd::AsFd;
+    use std::time::Instant;
+
+    let mut out = stdout();
+    write!(
+        out,
+        "\x1b_Gi=31,s=1,v=1,a=q,t=d,f=24;AAAA\x1b\\\x1b_Gi=32,s=1,v=1,a=q,t=f,f=24;{}\x1b\\\x1b[>q\x1b[c",
+        STANDARD.encode(probe.to_string_lossy().as_bytes())
+    )
+    .ok()?;
+    out.flush().ok()?;
+
+    let stdin = std::io::stdin();
+    let fd = stdin.as_fd();
+    let deadline = Instant::now() + QUERY_TIMEOUT;
+    let mut reply = Vec::new();
+    while device_attributes(&String::from_utf8_lossy(&reply)).is_none() {
+        let timeout = Timespec::try_from(deadline.checked_duration_since(Instant::now())?).ok()?;
+        let mut fds = [PollFd::new(&fd, PollFlags::IN)];
+        if poll(&mut fds, Some(&timeout)).ok()? == 0 {
+            debug!("terminal didn't answer the graphics queries");
+            return None;
+        }
+        let mut buf = [0u8; 256];
+        let n = rustix::io::read(fd, &mut buf).ok()?;
+        if n == 0 {
+            return None;
+        }
+        reply.extend_from_slice(&buf[..n]);
+    }
+
+    Some(String::from_utf8_lossy(&reply).into_owned())
+}
+
+#[cfg(not(unix))]
+fn query_terminal() -> Option<String> {
+    None
+}
+
+/// Attributes of a primary device attributes reply, `ESC [ ? 62 ; 4 c`.
+fn device_attributes(reply: &str) -> Option<Vec<u32>> {
+    let start = reply.find("\x1b[?")? + 3;
+    let rest = &reply[start..];
+    let end = rest.find(|c: char| !c.is_ascii_digit() && c != ';')?;
+    if !rest[end..].starts_with('c') {
+        return None;
+    }
+    Some(
+        rest[..end]
+            .split(';')
+            .filter_map(|attr| attr.parse().ok())
+            .collect(),
+    )
+}
+
+fn id_color(id: u32) -> Color {
+    Color::Rgb((id >> 16) as u8, (id >> 8) as u8, id as u8)
+}
+
+/// Style of row `row` of an avatar slot. Views draw avatars as blank cells in this style
+/// and `Graphics::paint` puts the image there, the row tells stacked slots of one author apart.
+pub fn slot_style(id: u32, row: u16) -> Style {
+    Style::default()
+        .fg(id_color(id))
+        .underline_color(Color::Indexed(row as u8))
+        .add_modifier(Modifier::HIDDEN)
+}
+
+/// The slot row `cell` belongs to, as `(avatar id, row)`.
+fn slot_at(buf: &Buffer, x: u16, y: u16) -> Option<(u32, u16)> {
+    let cell = &buf[(x, y)];
+    match (
+        cell.modifier.contains(Modifier::HIDDEN),
+        cell.fg,
+        cell.underline_color,
+    ) {
+        (true, Color::Rgb(r, g, b), Color::Indexed(row)) => {
+            Some(((r as u32) << 16 | (g as u32) << 8 | b as u32, row as u16))
+        }
+        _ => None,
+    }
+}
+
+/// An avatar slot found in the frame, in cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Placement {
+    id: u32,
+    x: u16,
+    y: u16,
+    cols: u16,
+    rows: u16,
+}
+
+fn find_placements(buf: &Buffer) -> Vec<Placement> {
+    let area = buf.area;
+    let mut placements = Vec::new();
+    for y in area.top()..area.bottom() {
+        for x in area.left()..area.right() {
+            let Some((id, 0)) = slot_at(buf, x, y) else {
+                continue;
+            };
+            if x > area.left() && slot_at(buf, x - 1, y) == Some((id, 0)) {
+                continue;
+            }
+            let cols = (x..area.right())
+                .take_while(|&cx| slot_at(buf, cx, y) == Some((id, 0)))
+                .count() as u16;
+            let rows = (y..area.bottom())
+                .enumerate()
+                .take_while(|&(row, cy)| slot_at(buf, x, cy) == Some((id, row as u16)))
+                .count() as u16;
+            placements.push(Placement {
+                id,
+                x,
+                y,
+                cols,
+                rows,
+            });
+        }
+    }
+    placements
+}
+
+/// Draws avatars with the terminal's graphics backend.
+pub struct Graphics {
+    backend: Backend,
+    avatars: HashMap<u32, Arc<Avatar>>,
+    pixels: HashMap<u32, Option<RgbaImage>>,
+    /// Kitty image ids already sent, one per avatar and slot size.
+    transmitted: HashSet<u32>,
+    /// Slot sizes in kitty image ids, the index is the id's high byte. `None` once no
+    /// image uses it anymore.
+    kitty_sizes: Vec<Option<(u16, u16)>>,
+    /// Encoded sixel or iTerm2 images by avatar id and size in pixels.
+    encoded: HashMap<(u32, u32, u32), Arc<String>>,
+    /// Sixel and iTerm2 images are written after the frame, at these slots.
+    pending: Vec<Placement>,
+    /// Sixel and iTerm2 images on screen. ratatui leaves their cells alone while the slot
+    /// stays put, so they're only written again once it moved.
+    written: HashSet<Placement>,
+    /// Size of the last painted frame. A new size means the terminal was cleared.
+    area: Rect,
+}
+
+impl Graphics {
+    pub fn new(backend: Backend) -> Self {
+        Self {
+            backend,
+            avatars: HashMap::new(),
+            pixels: HashMap::new(),
+            transmitted: HashSet::new(),
+            kitty_sizes: Vec::new(),
+            encoded: HashMap::new(),
+            pending: Vec::new(),
+            written: HashSet::new(),
+            area: Rect::default(),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.backend != Backend::None
+    }
+
+    pub fn register(&mut self, avatar: &Arc<Avatar>) {
+        self.avatars
+            .entry(avatar.id)
+            .or_insert_with(|| avatar.clone());
+    }
+
+    /// Forgets avatars not in `live`, kitty images of them are deleted in the terminal.
+    pub fn retain(&mut self, live: &HashSet<u32>) {
+        self.avatars.retain(|id, _| live.contains(id));
+        self.pixels.retain(|id, _| live.contains(id));
+        self.encoded.retain(|(id, _, _), _| live.contains(id));
+
+        self.delete_kitty_images(live);
+    }
+
+    /// Deletes the kitty images of avatars not in `live` in the terminal.
+    fn delete_kitty_images(&mut self, live: &HashSet<u32>) {
+        let gone = self.forget_kitty_images(live);
+        if gone.is_empty() {
+            return;
+        }
+        debug!("deleting {} kitty images", gone.len());
+        if let Err(e) = write_stdout(&kitty_delete_commands(&gone)) {
+            debug!("kitty delete failed err={:#}", e);
+        }
+    }
+
+    /// Drops the kitty images of avatars not in `live` from `transmitted` and frees the
+    /// slot sizes no image uses anymore. The dropped image ids are returned, sorted.
+    fn forget_kitty_images(&mut self, live: &HashSet<u32>) -> Vec<u32> {
+        let mut gone: Vec<u32> = self
+            .transmitted
+            .iter()
+            .copied()
+            .filter(|image_id| !live.contains(&(image_id & KITTY_AVATAR_ID)))
+            .collect();
+        gone.sort_unstable();
+        for image_id in &gone {
+            self.transmitted.remove(image_id);
+        }
+        for (variant, size) in self.kitty_sizes.iter_mut().enumerate() {
+            if !self
+                .transmitted
+                .iter()
+                .any(|image_id| (image_id >> 24) as usize == variant)
+            {
+                *size = None;
+            }
+        }
+        gone
+    }
+
+    /// Forgets every avatar so the terminal doesn't keep our images.
+    fn clear(&mut self) {
+        self.retain(&HashSet::new());
+    }
+
+    /// Turns the avatar slots of a drawn frame into images, before it's flushed.
+    pub fn paint(&mut self, buf: &mut Buffer) {
+        self.pending.clear();
+        if buf.area != self.area {
+            // ratatui clears the screen on resize, which some terminals take as dropping
+            // images. Others keep them, so they're deleted before being sent again.
+            self.area = buf.area;
+            self.delete_kitty_images(&HashSet::new());
+            self.written.clear();
+        }
+        for placement in find_placements(buf) {
+            if !self.avatars.contains_key(&placement.id) {
+                continue;
+            }
+            match self.backend {
+                Backend::Kitty(transfer) => self.paint_kitty(buf, placement, transfer),
+                Backend::HalfBlock => self.paint_half_blocks(buf, placement),
+                Backend::Sixel | Backend::Iterm2 => self.pending.push(placement),
+                Backend::None => {}
+            }
+            // the slot keeps its color so a different avatar in the same place redraws the cells
+            for y in placement.y..placement.y + placement.rows {
+                for x in placement.x..placement.x + placement.cols {
+                    let cell = &mut buf[(x, y)];
+                    cell.modifier.remove(Modifier::HIDDEN);
+                    cell.underline_color = Color::Reset;
+                }
+            }
+        }
+    }
+
+    /// Writes the sixel or iTerm2 images of the last painted frame that aren't on screen
+    /// yet, after it was flushed.
+    pub fn flush_images(&mut self) -> anyhow::Result<()> {
+        let placements = std::mem::take(&mut self.pending);
+        let new: Vec<Placement> = placements
+            .iter()
+            .copied()
+            .filter(|placement| !self.written.contains(placement))
+            .collect();
+        // slots that moved or went away had their cells redrawn, which erased the image
+        self.written = placements.into_iter().collect();
+        if new.is_empty() {
+            return Ok(());
+        }
+
+        let (cell_width, cell_height) = cell_pixels();
+        let mut out = stdout();
+        write!(out, "\x1b7")?;
+        for placement in new {
+            let (width, height) = (
+                placement.cols as u32 * cell_width,
+                placement.rows as u32 * cell_height,
+            );
+            let image = match self.backend {
+                Backend::Sixel => self.encoded(placement.id, width, height, sixel),
+                Backend::Iterm2 => self.encoded(placement.id, width, height, |img| {
+                    iterm2(img, placement.cols, placement.rows)
+                }),
+                _ => None,
+            };
+            if let Some(image) = image {
+                write!(
+                    out,
+                    "\x1b[{};{}H{}",
+                    placement.y + 1,
+                    placement.x + 1,
+                    image
+                )?;
+            }
+        }
+        write!(out, "\x1b8")?;
+        out.flush()?;
+        Ok(())
+    }
+
+    fn paint_kitty(&mut self, buf: &mut Buffer, placement: Placement, transfer: KittyTransfer) {
+        let size = (placement.cols, placement.rows);
+        let variant = match self.kitty_sizes.iter().position(|s| *s == Some(size)) {
+            Some(i) => i,
+            None => match self.kitty_sizes.iter().position(Option::is_none) {
+                Some(i) => {
+                    self.kitty_sizes[i] = Some(size);
+                    i
+                }
+                None if self.kitty_sizes.len() < PLACEHOLDER_DIACRITICS.len() => {
+                    self.kitty_sizes.push(Some(size));
+                    self.kitty_sizes.len() - 1
+                }
+                None => {
+                    debug!("no kitty image id left for slot size {:?}", size);
+                    return;
+                }
+            },
+        };
+        let image_id = placement.id | (variant as u32) << 24;
+        if !self.transmitted.contains(&image_id) {
+            let avatar = self.avatars[&placement.id].clone();
+            let result = match transfer {
+                KittyTransfer::File => transmit_kitty(&avatar, image_id, size, None),
+                KittyTransfer::Direct => match self.pixels(placement.id) {
+                    Some(pixels) => transmit_kitty(&avatar, image_id, size, Some(pixels)),
+                    None => return,
+                },
+            };
+            match result {
+                Ok(()) => {
+                    self.transmitted.insert(image_id);
+                }
+                Err(e) => debug!("kitty transmit failed id={} err={:#}", image_id, e),
+            }
+        }
+
+        for row in 0..placement.rows.min(PLACEHOLDER_DIACRITICS.len() as u16) {
+            for col in 0..placement.cols.min(PLACEHOLDER_DIACRITICS.len() as u16) {
+                let symbol: String = [
+                    KITTY_PLACEHOLDER,
+                    PLACEHOLDER_DIACRITICS[row as usize],
+                    PLACEHOLDER_DIACRITICS[col as usize],
+                    PLACEHOLDER_DIACRITICS[variant],
+                ]
+                .into_iter()
+                .collect();
+                buf[(placement.x + col, placement.y + row)].set_symbol(&symbol);
+            }
+        }
+    }
+
+    /// Two pixels per cell, the upper half block in the top one's color on the bottom one's.
+    fn paint_half_blocks(&mut self, buf: &mut Buffer, placement: Placement) {
+        let Some(pixels) = self.pixels(placement.id) else {
+            return;
+        };
+        let small = image::imageops::resize(
+            pixels,
+            placement.cols as u32,
+            placement.rows as u32 * 2,
+            FilterType::Triangle,
+        );
+        for row in 0..placement.rows {
+            for col in 0..placement.cols {
+                let top = small.get_pixel(col as u32, row as u32 * 2);
+                let bottom = small.get_pixel(col as u32, row as u32 * 2 + 1);
+                buf[(placement.x + col, placement.y + row)]
+                    .set_symbol("▀")
+                    .set_fg(Color::Rgb(top[0], top[1], top[2]))
+                    .set_bg(Color::Rgb(bottom[0], bottom[1], bottom[2]));
+            }
+        }
+    }
+
+    fn pixels(&mut self, id: u32) -> Option<&RgbaImage> {
+        let avatar = self.avatars.get(&id)?;
+        self.pixels
+            .entry(id)
+            .or_insert_with(|| {
+                let image = std::fs::read(&avatar.path)
+                    .ok()
+                    .and_then(|raw| RgbaImage::from_raw(avatar.width, avatar.height, raw));
+                if image.is_none() {
+                    debug!("unreadable avatar pixels path={}", avatar.path);
+                }
+                image
+            })
+            .as_ref()
+    }
+
+    fn encoded(
+        &mut self,
+        id: u32,
+        width: u32,
+        height: u32,
+        encode: impl FnOnce(&RgbaImage) -> String,
+    ) -> Option<Arc<String>> {
+        if let Some(image) = self.encoded.get(&(id, width, height)) {
+            return Some(image.clone());
+        }
+        let pixels = self.pixels(id)?;
+        let image = Arc::new(encode(&image::imageops::resize(
+            pixels,
+            width,
+            height,
+            FilterType::Lanczos3,
+        )));
+        self.encoded.insert((id, width, height), image.clone());
+        Some(image)
+    }
+}
+
+// the terminal keeps kitty images after we exit, also when that's on an error or a panic
+impl Drop for Graphics {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+/// Sends the avatar as kitty image `image_id` with a virtual placement of `cols` x `rows`
+/// cells. The terminal reads the avatar's file unless `pixels` are given to send inline.
+fn transmit_kitty(
+    avatar: &Avatar,
+    image_id: u32,
+    (cols, rows): (u16, u16),
+    pixels: Option<&RgbaImage>,
+) -> anyhow::Result<()> {
+    let (medium, payload) = match pixels {
+        Some(pixels) => ('d', STANDARD.encode(pixels.as_raw())),
+        None => ('f', STANDARD.encode(avatar.path.as_bytes())),
+    };
+    let mut out = stdout();
+    write!(
+        out,
+        "\x1b_Ga=T,U=1,t={},f=32,s={},v={},i={},c={},r={},q=2",
+        medium, avatar.width, avatar.height, image_id, cols, rows,
+    )?;
+    // inline data goes in chunks, every one but the last marked with m=1
+    let mut chunks = payload.as_bytes().chunks(KITTY_CHUNK).peekable();
+    while let Some(chunk) = chunks.next() {
+        let more = chunks.peek().is_some() as u8;
+        out.write_all(format!(",m={};", more).as_bytes())?;
+        out.write_all(chunk)?;
+        out.write_all(b"\x1b\\")?;
+        if more == 1 {
+            write!(out, "\x1b_Gq=2")?;
+        }
+    }
+    Ok(())
+}
+
+/// Commands deleting kitty images and freeing their data in the terminal.
+fn kitty_delete_commands(image_ids: &[u32]) -> String {
+    image_ids
+        .iter()
+        .map(|image_id| format!("\x1b_Ga=d,d=I,i={},q=2\x1b\\", image_id))
+        .collect()
+}
+
+fn write_stdout(commands: &str) -> anyhow::Result<()> {
+    let mut out = stdout();
+    out.write_all(commands.as_bytes())?;
+    out.flush()?;
+    Ok(())
+}
+
+fn cell_pixels() -> (u32, u32) {
+    match window_size() {
+        Ok(size) if size.width > 0 && size.height > 0 && size.columns > 0 && size.rows > 0 => (
+            (size.width / size.columns) as u32,
+            (size.height / size.rows) as u32,
+        ),
+        _ => DEFAULT_CELL_PIXELS,
+    }
+}
+
+/// Sixel data for `img`, colors snapped to a 6x6x6 cube and transparent pixels left unset.
+fn sixel(img: &RgbaImage) -> String {
+    let level = |v: u8| (v as u32 * 5 + 127) / 255;
+    let color = |x: u32, y: u32| {
+        let p = img.get_pixel(x, y);
+        (p[3] >= 128).then(|| level(p[0]) * 36 + level(p[1]) * 6 + level(p[2]))
+    };
+
+    let (width, height) = img.dimensions();
+    let mut palette: Vec<u32> = img
+        .enumerate_pixels()
+        .filter_map(|(x, y, _)| color(x, y))
+        .collect();
+    palette.sort_unstable();
+    palette.dedup();
+
+    let mut out = format!("\x1bP0;1;0q\"1;1;{};{}", width, height);
+    for index in palette {
+        let percent = |v: u32| v * 100 / 5;
+        let _ = write!(
+            out,
+            "#{};2;{};{};{}",
+            index,
+            percent(index / 36),
+            percent(index / 6 % 6),
+            percent(index % 6)
+        );
+    }
+
+    for band in (0..height).step_by(6) {
+        let rows = band..(band + 6).min(height);
+        let mut used: Vec<u32> = (0..width)
+            .flat_map(|x| rows.clone().filter_map(move |y| color(x, y)))
+            .collect();
+        used.sort_unstable();
+        used.dedup();
+
+        for (i, &index) in used.iter().enumerate() {
+            if i > 0 {
+                out.push('$');
+            }
+            let _ = write!(out, "#{}", index);
+            let mut run: Option<(char, usize)> = None;
+            for x in 0..width {
+                let bits = rows
+                    .clone()
+                    .filter(|&y| color(x, y) == Some(index))
+                    .fold(0u8, |bits, y| bits | 1 << (y - band));
+                let c = (63 + bits) as char;
+                run = match run {
+                    Some((prev, n)) if prev == c => Some((prev, n + 1)),
+                    Some((prev, n)) => {
+                        push_sixel_run(&mut out, prev, n);
+                        Some((c, 1))
+                    }
+                    None => Some((c, 1)),
+                };
+            }
+            if let Some((prev, n)) = run {
+                push_sixel_run(&mut out, prev, n);
+            }
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\");
+    out
+}
+
+fn push_sixel_run(out: &mut String, c: char, n: usize) {
+    if n > 3 {
+        let _ = write!(out, "!{}{}", n, c);
+    } else {
+        out.extend(std::iter::repeat_n(c, n));
+    }
+}
+
+/// An iTerm2 inline image of `img` as PNG, stretched over `cols` x `rows` cells.
+fn iterm2(img: &RgbaImage, cols: u16, rows: u16) -> String {
+    let mut png = Cursor::new(Vec::new());
+    if let Err(e) = img.write_to(&mut png, ImageFormat::Png) {
+        debug!("avatar png encoding failed: {:#}", e);
+        return String::new();
+    }
+    let png = png.into_inner();
+    format!(
+        "\x1b]1337;File=inline=1;size={};width={};height={};preserveAspectRatio=0:{}\x07",
+        png.len(),
+        cols,
+        rows,
+        STANDARD.encode(&png)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forgotten_kitty_images_are_deleted() {
+        let mut graphics = Graphics::new(Backend::Kitty(KittyTransfer::Direct));
+        let (kept, dropped) = (1, 2);
+        graphics.transmitted = HashSet::from([kept, dropped, dropped | 1 << 24]);
+        graphics.kitty_sizes = vec![Some((2, 1)), Some((8, 4))];
+
+        let gone = graphics.forget_kitty_images(&HashSet::from([kept]));
+        assert_eq!(gone, vec![dropped, dropped | 1 << 24]);
+        assert_eq!(graphics.transmitted, HashSet::from([kept]));
+        // the larger size has no image left and can go to a new one
+        assert_eq!(graphics.kitty_sizes, vec![Some((2, 1)), None]);
+        assert_eq!(
+            kitty_delete_commands(&gone),
+            "\x1b_Ga=d,d=I,i=2,q=2\x1b\\\x1b_Ga=d,d=I,i=16777218,q=2\x1b\\"
+        );
+
+        // what `clear` and a resize delete
+        assert_eq!(graphics.forget_kitty_images(&HashSet::new()), vec![kept]);
+        assert!(graphics.transmitted.is_empty());
+        assert_eq!(graphics.kitty_sizes, vec![None, None]);
+    }
+}
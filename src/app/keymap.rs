@@ -0,0 +1,269 @@
+use anyhow::bail;
+use ratatui::crossterm::event::KeyCode;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Where a key is pressed, the same key can do different things in the chat and in
+/// selection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyMode {
+    Chat,
+    Selection,
+}
+
+/// What a key does outside of input, search and dialogs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyAction {
+    Quit,
+    Compose,
+    /// Starts the selection, and leaves it again.
+    Select,
+    TogglePoll,
+    ErrorLog,
+    ScrollUp,
+    ScrollDown,
+    PageUp,
+    PageDown,
+    Top,
+    Bottom,
+    Search,
+    NextMatch,
+    PrevMatch,
+    CycleFilter,
+    CycleTimeStyle,
+    NextStream,
+    PrevStream,
+    CycleLayout,
+    ShowAuthor,
+    FilterAuthor,
+    Delete,
+    Timeout,
+    Ban,
+}
+
+impl KeyAction {
+    /// Name as written under `[keys]` in the config.
+    fn name(self) -> &'static str {
+        match self {
+            KeyAction::Quit => "quit",
+            KeyAction::Compose => "compose",
+            KeyAction::Select => "select",
+            KeyAction::TogglePoll => "toggle_poll",
+            KeyAction::ErrorLog => "error_log",
+            KeyAction::ScrollUp => "scroll_up",
+            KeyAction::ScrollDown => "scroll_down",
+            KeyAction::PageUp => "page_up",
+            KeyAction::PageDown => "page_down",
+            KeyAction::Top => "top",
+            KeyAction::Bottom => "bottom",
+            KeyAction::Search => "search",
+            KeyAction::NextMatch => "next_match",
+            KeyAction::PrevMatch => "prev_match",
+            KeyAction::CycleFilter => "cycle_filter",
+            KeyAction::CycleTimeStyle => "cycle_time_style",
+            KeyAction::NextStream => "next_stream",
+            KeyAction::PrevStream => "prev_stream",
+            KeyAction::CycleLayout => "cycle_layout",
+            KeyAction::ShowAuthor => "show_author",
+            KeyAction::FilterAuthor => "filter_author",
+            KeyAction::Delete => "delete",
+            KeyAction::Timeout => "timeout",
+            KeyAction::Ban => "ban",
+        }
+    }
+
+    /// Modes the action's keys work in. Keys only clash within a mode.
+    fn modes(self) -> &'static [KeyMode] {
+        use KeyAction::*;
+        match self {
+            // the app handles these before the view sees the key
+            CycleTimeStyle | NextStream | PrevStream | CycleLayout => {
+                &[KeyMode::Chat, KeyMode::Selection]
+            }
+            // scrolling moves the selection
+            Select | ScrollUp | ScrollDown => &[KeyMode::Chat, KeyMode::Selection],
+            ShowAuthor | FilterAuthor | Delete | Timeout | Ban => &[KeyMode::Selection],
+            Quit | Compose | TogglePoll | ErrorLog | PageUp | PageDown | Top | Bottom | Search
+            | NextMatch | PrevMatch | CycleFilter => &[KeyMode::Chat],
+        }
+    }
+
+    fn works_in(self, mode: KeyMode) -> bool {
+        self.modes().contains(&mode)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct KeyMap {
+    bindings: Vec<(KeyCode, KeyAction)>,
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        use KeyAction::*;
+        Self {
+            bindings: vec![
+                (KeyCode::Esc, Quit),
+                (KeyCode::Char('q'), Quit),
+                (KeyCode::Char('i'), Compose),
+                (KeyCode::Char('s'), Select),
+                (KeyCode::Char('p'), TogglePoll),
+                (KeyCode::Char('e'), ErrorLog),
+                (KeyCode::Up, ScrollUp),
+                (KeyCode::Down, ScrollDown),
+                (KeyCode::PageUp, PageUp),
+                (KeyCode::PageDown, PageDown),
+                (KeyCode::Home, Top),
+                (KeyCode::End, Bottom),
+                (KeyCode::Char('/'), Search),
+                (KeyCode::Char('n'), NextMatch),
+                (KeyCode::Char('N'), PrevMatch),
+                (KeyCode::Char('f'), CycleFilter),
+                (KeyCode::Char('T'), CycleTimeStyle),
+                (KeyCode::Tab, NextStream),
+                (KeyCode::BackTab, PrevStream),
+                (KeyCode::Char('L'), CycleLayout),
+                (KeyCode::Enter, ShowAuthor),
+                (KeyCode::Char('a'), FilterAuthor),
+                (KeyCode::Char('d'), Delete),
+                (KeyCode::Char('t'), Timeout),
+                (KeyCode::Char('b'), Ban),
+            ],
+        }
+    }
+}
+
+impl KeyMap {
+    /// The default bindings with every action listed in `overrides` rebound to its keys.
+    pub fn with_overrides(overrides: &HashMap<KeyAction, Vec<String>>) -> anyhow::Result<Self> {
+        let mut bindings: Vec<(KeyCode, KeyAction)> = KeyMap::default()
+            .bindings
+            .into_iter()
+            .filter(|(_, action)| !overrides.contains_key(action))
+            .collect();
+
+        for (action, keys) in overrides {
+            for key in keys {
+                let Some(code) = parse_key(key) else {
+                    bail!("unknown key \"{}\" for {}", key, action.name());
+                };
+                if code == KeyCode::Esc && action.works_in(KeyMode::Selection) {
+                    bail!(
+                        "key \"{}\" always leaves the selection and can't be bound to {}",
+                        key,
+                        action.name()
+                    );
+                }
+                let clash = bindings.iter().find(|(bound, other)| {
+                    *bound == code && other.modes().iter().any(|mode| action.works_in(*mode))
+                });
+                if let Some((_, other)) = clash {
+                    bail!(
+                        "key \"{}\" is bound to both {} and {}",
+                        key,
+                        other.name(),
+                        action.name()
+                    );
+                }
+                bindings.push((code, *action));
+            }
+        }
+
+        Ok(Self { bindings })
+    }
+
+    pub fn action(&self, mode: KeyMode, code: KeyCode) -> Option<KeyAction> {
+        self.bindings
+            .iter()
+            .find(|(bound, action)| *bound == code && action.works_in(mode))
+            .map(|(_, action)| *action)
+    }
+
+    /// Keys bound to `action` for the help line, e.g. "ESC/q".
+    pub fn label(&self, action: KeyAction) -> String {
+        self.bindings
+            .iter()
+            .filter(|(_, bound)| *bound == action)
+            .map(|(code, _)| key_label(*code))
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+/// Parses a key as written in the config, a single character or a name like "PageUp".
+fn parse_key(key: &str) -> Option<KeyCode> {
+    let mut chars = key.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(KeyCode::Char(c));
+    }
+
+    let code = match key.to_ascii_lowercase().as_str() {
+        "esc" | "escape" => KeyCode::Esc,
+        "enter" => KeyCode::Enter,
+        "space" => KeyCode::Char(' '),
+        "tab" => KeyCode::Tab,
+        "backtab" => KeyCode::BackTab,
+        "backspace" => KeyCode::Backspace,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "pageup" | "pgup" => KeyCode::PageUp,
+        "pagedown" | "pgdn" => KeyCode::PageDown,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        f => KeyCode::F(f.strip_prefix('f')?.parse().ok()?),
+    };
+    Some(code)
+}
+
+fn key_label(code: KeyCode) -> String {
+    match code {
+        KeyCode::Char(' ') => "Space".to_string(),
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::Esc => "ESC".to_string(),
+        KeyCode::PageUp => "PgUp".to_string(),
+        KeyCode::PageDown => "PgDn".to_string(),
+        KeyCode::BackTab => "S-Tab".to_string(),
+        code => code.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overrides(bindings: &[(KeyAction, &str)]) -> HashMap<KeyAction, Vec<String>> {
+        let mut overrides: HashMap<KeyAction, Vec<String>> = HashMap::new();
+        for (action, key) in bindings {
+            overrides.entry(*action).or_default().push(key.to_string());
+        }
+        overrides
+    }
+
+    #[test]
+    fn keys_clash_only_within_a_mode() {
+        let keys = KeyMap::with_overrides(&overrides(&[(KeyAction::Ban, "q")])).unwrap();
+        assert_eq!(
+            keys.action(KeyMode::Chat, KeyCode::Char('q')),
+            Some(KeyAction::Quit)
+        );
+        assert_eq!(
+            keys.action(KeyMode::Selection, KeyCode::Char('q')),
+            Some(KeyAction::Ban)
+        );
+
+        let keys = KeyMap::with_overrides(&overrides(&[(KeyAction::Select, "v")])).unwrap();
+        assert_eq!(
+            keys.action(KeyMode::Selection, KeyCode::Char('v')),
+            Some(KeyAction::Select)
+        );
+        assert_eq!(keys.action(KeyMode::Selection, KeyCode::Char('s')), None);
+
+        // app-wide keys work in selection too
+        assert!(KeyMap::with_overrides(&overrides(&[(KeyAction::CycleLayout, "a")])).is_err());
+        assert!(KeyMap::with_overrides(&overrides(&[(KeyAction::Delete, "Tab")])).is_err());
+        assert!(KeyMap::with_overrides(&overrides(&[(KeyAction::Ban, "Esc")])).is_err());
+    }
+}
@@ -1,85 +1,517 @@
-use crate::app::event::{AppEvent, KittyAvatar};
-use crate::app::state::{AppState, ScrollState, Stats};
-use crate::app::ui::{draw, max_scroll_for_viewport};
-use base64::{Engine as _, engine::general_purpose::STANDARD};
+use crate::app::author::{ArchivedAuthor, MAX_AUTHOR_MESSAGES};
+use crate::app::event::{
+    AppError, AppEvent, Avatar, ChatMessage, Command, DisconnectReason, OutgoingResult,
+    StatusEvent, StreamEvent, StreamId,
+};
+use crate::app::graphics::{Backend, Graphics};
+use crate::app::keymap::{KeyAction, KeyMode};
+use crate::app::state::{Action, AppState, LOCAL_ID_PREFIX};
+use crate::app::time::TimeFormat;
+use crate::app::ui::{
+    author_max_scroll, draw, draw_tabs, layout, max_scroll_for_viewport, message_scroll_range,
+};
+use crate::archive::{Archive, ArchivedMessage};
+use crate::config::Config;
+use crate::youtube::api::chat_message;
+use crate::youtube::error::app_error;
+use clap::ValueEnum;
 use ratatui::Terminal;
 use ratatui::backend::CrosstermBackend;
-use std::collections::HashSet;
-use std::io::{Stdout, Write, stdout};
+use ratatui::crossterm::event::KeyEvent;
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use std::collections::{HashMap, HashSet};
+use std::io::Stdout;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
 use tokio::sync::mpsc;
 
+pub mod author;
 pub mod event;
+pub mod filter;
+pub mod graphics;
+pub mod keymap;
+pub mod search;
 pub mod state;
+pub mod theme;
+pub mod time;
 mod ui;
 
-struct Graphics {
-    kitty_supported: bool,
-    loaded_avatar_ids: HashSet<u32>,
+const HISTORY_PAGE_SIZE: usize = 100;
+
+/// How several streams share the screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum StreamLayout {
+    /// One pane per stream, side by side.
+    Tiled,
+    /// One stream at a time, switched with Tab.
+    Tabbed,
+    /// All chats interleaved in a single pane.
+    Merged,
+}
+
+impl StreamLayout {
+    fn next(self) -> Self {
+        match self {
+            StreamLayout::Tiled => StreamLayout::Tabbed,
+            StreamLayout::Tabbed => StreamLayout::Merged,
+            StreamLayout::Merged => StreamLayout::Tiled,
+        }
+    }
+}
+
+/// One of the chat views, a stream's own or the merged one.
+#[derive(Debug, Clone, Copy)]
+pub enum View {
+    Stream(StreamId),
+    Merged,
 }
+
+/// A watched stream: its title and, in write mode, where to send commands.
+pub struct StreamTarget {
+    pub title: String,
+    pub video_id: String,
+    pub commands: Option<mpsc::Sender<Command>>,
+}
+
 pub struct App {
-    pub state: AppState,
+    /// Per-stream state, indexed by `StreamId`.
+    streams: Vec<AppState>,
+    /// Every stream's events interleaved, backs the merged layout.
+    merged: AppState,
+    /// `None` entries unless started in write mode.
+    commands: Vec<Option<mpsc::Sender<Command>>>,
+    video_ids: Vec<String>,
+    /// Read side of the chat archive, `None` unless archiving is enabled. Only queried on
+    /// blocking threads, the results come back as events.
+    archive: Option<Arc<Mutex<Archive>>>,
+    tx: mpsc::Sender<AppEvent>,
+    layout: StreamLayout,
+    /// Stream that receives keys in the tiled and tabbed layouts and outgoing messages in all.
+    focused: StreamId,
     graphics: Graphics,
+    /// Avatars that arrived after their author's first messages, by channel id.
+    late_avatars: HashMap<String, Arc<Avatar>>,
+    next_local_id: u64,
 }
 
 impl App {
-    pub fn new(title: String) -> Self {
+    pub fn new(
+        targets: Vec<StreamTarget>,
+        layout: StreamLayout,
+        archive: Option<Archive>,
+        time_format: TimeFormat,
+        config: &Config,
+        graphics: Backend,
+        tx: mpsc::Sender<AppEvent>,
+    ) -> Self {
+        let write_enabled = targets.iter().any(|t| t.commands.is_some());
+        let mut merged = AppState::new(
+            targets
+                .iter()
+                .map(|t| t.title.as_str())
+                .collect::<Vec<_>>()
+                .join(" + "),
+            write_enabled,
+            time_format,
+            config,
+        );
+        merged.show_stream_badges = true;
+
+        let video_ids = targets.iter().map(|t| t.video_id.clone()).collect();
+        let (streams, commands) = targets
+            .into_iter()
+            .map(|t| {
+                (
+                    AppState::new(t.title, t.commands.is_some(), time_format, config),
+                    t.commands,
+                )
+            })
+            .unzip();
+
         Self {
-            state: AppState {
-                title,
-                messages: Default::default(),
-                scroll_state: ScrollState {
-                    scroll_offset: 0,
-                    auto_scroll: true,
-                    visible_rows: 1,
-                    max_scroll_rows: 0,
-                },
-                stats: Stats { viewer_count: 0 },
-            },
-            graphics: Graphics {
-                kitty_supported: std::env::var("TERM")
-                    .map(|term| matches!(term.as_str(), "xterm-kitty"))
-                    .unwrap_or(false),
-                loaded_avatar_ids: HashSet::new(),
-            },
+            streams,
+            merged,
+            commands,
+            video_ids,
+            archive: archive.map(|archive| Arc::new(Mutex::new(archive))),
+            tx,
+            layout,
+            focused: 0,
+            graphics: Graphics::new(graphics),
+            late_avatars: HashMap::new(),
+            next_local_id: 0,
         }
     }
 
-    pub fn on_event(&mut self, event: AppEvent) -> bool {
+    fn is_multi_stream(&self) -> bool {
+        self.streams.len() > 1
+    }
+
+    fn active_view(&self) -> View {
+        if self.is_multi_stream() && self.layout == StreamLayout::Merged {
+            View::Merged
+        } else {
+            View::Stream(self.focused)
+        }
+    }
+
+    fn view(&self, view: View) -> &AppState {
+        view_state(&self.streams, &self.merged, view)
+    }
+
+    fn view_mut(&mut self, view: View) -> &mut AppState {
+        match view {
+            View::Stream(id) => &mut self.streams[id],
+            View::Merged => &mut self.merged,
+        }
+    }
+
+    /// Applies `f` to the stream's own state and to the merged one.
+    fn update_stream(&mut self, stream: StreamId, f: impl Fn(&mut AppState)) {
+        f(&mut self.streams[stream]);
+        f(&mut self.merged);
+    }
+
+    /// Lets go of avatars whose authors have no messages left in any view.
+    fn release_avatars(&mut self) {
+        let live: HashSet<u32> = self
+            .streams
+            .iter()
+            .chain([&self.merged])
+            .flat_map(AppState::avatar_ids)
+            .collect();
+        self.graphics.retain(&live);
+        self.late_avatars
+            .retain(|_, avatar| live.contains(&avatar.id));
+    }
+
+    fn on_action(&mut self, action: Action) -> bool {
+        match action {
+            Action::Quit => return true,
+            Action::SendMessage(text) => {
+                let stream = self.focused;
+                self.next_local_id += 1;
+                let local_id = format!("{}{}", LOCAL_ID_PREFIX, self.next_local_id);
+                self.update_stream(stream, |state| {
+                    state.push_local_echo(stream, local_id.clone(), text.clone())
+                });
+
+                let Some(commands) = &self.commands[stream] else {
+                    return false;
+                };
+                if let Err(e) = commands.try_send(Command::SendMessage {
+                    local_id: local_id.clone(),
+                    text,
+                }) {
+                    let error = app_error("send message", &e.into());
+                    self.update_stream(stream, |state| {
+                        state.apply_outgoing(OutgoingResult::Failed {
+                            local_id: local_id.clone(),
+                            error: error.clone(),
+                        })
+                    });
+                }
+            }
+            Action::Moderate { stream, command } => {
+                let Some(commands) = &self.commands[stream] else {
+                    return false;
+                };
+                if let Err(e) = commands.try_send(command) {
+                    let view = self.active_view();
+                    self.view_mut(view)
+                        .push_error(app_error("moderation", &e.into()));
+                }
+            }
+            Action::LoadHistory => self.load_history(),
+            Action::ShowAuthor { channel_id } => self.show_author(channel_id),
+        }
+
+        false
+    }
+
+    /// Pages older messages of the active view in from the archive, see `AppEvent::History`.
+    fn load_history(&mut self) {
+        let view = self.active_view();
+        let Some(archive) = self.archive.clone() else {
+            self.view_mut(view).history_exhausted = true;
+            return;
+        };
+        let state = self.view_mut(view);
+        if state.history_loading {
+            return;
+        }
+        let Some(before_id) = state.oldest_message_id().map(str::to_string) else {
+            return;
+        };
+        state.history_loading = true;
+
+        let video_ids = self.video_ids.clone();
+        let watched: Vec<String> = match view {
+            View::Stream(id) => vec![video_ids[id].clone()],
+            View::Merged => video_ids.clone(),
+        };
+        let tx = self.tx.clone();
+        tokio::task::spawn_blocking(move || {
+            let watched: Vec<&str> = watched.iter().map(String::as_str).collect();
+            let messages = history_page(&lock(&archive), &video_ids, &watched, &before_id)
+                .map_err(|e| app_error("archive", &e));
+            let _ = tx.blocking_send(AppEvent::History {
+                view,
+                before_id,
+                messages,
+            });
+        });
+    }
+
+    /// Opens the author popup in the active view, once the archive was asked about them.
+    fn show_author(&mut self, channel_id: String) {
+        let view = self.active_view();
+        let Some(archive) = self.archive.clone() else {
+            self.view_mut(view).open_author(&channel_id, None);
+            return;
+        };
+
+        let (video_ids, focused, tx) = (self.video_ids.clone(), self.focused, self.tx.clone());
+        tokio::task::spawn_blocking(move || {
+            let archived = lock(&archive)
+                .author_history(&channel_id, MAX_AUTHOR_MESSAGES)
+                .map(|history| {
+                    // items from videos not watched right now are shown under the focused stream
+                    let convert = |a: &ArchivedMessage| {
+                        let stream = video_ids
+                            .iter()
+                            .position(|id| *id == a.video_id)
+                            .unwrap_or(focused);
+                        chat_message(&a.item, stream)
+                    };
+                    ArchivedAuthor {
+                        count: history.count,
+                        first_seen: history
+                            .first
+                            .as_ref()
+                            .and_then(convert)
+                            .map(|m| m.timestamp),
+                        messages: history.recent.iter().filter_map(convert).collect(),
+                    }
+                })
+                .map_err(|e| app_error("archive", &e));
+            let _ = tx.blocking_send(AppEvent::AuthorHistory {
+                view,
+                channel_id,
+                archived,
+            });
+        });
+    }
+
+    /// Keys that affect every view, like switching streams and layouts, `true` when the key was used.
+    fn handle_app_key(&mut self, key: KeyEvent) -> bool {
+        if self.view(self.active_view()).captures_input() {
+            return false;
+        }
+
+        let Some(action) = self.merged.keymap.action(KeyMode::Chat, key.code) else {
+            return false;
+        };
+        if action == KeyAction::CycleTimeStyle {
+            let mut time_format = self.merged.time_format;
+            time_format.cycle_style();
+            for state in self.streams.iter_mut().chain([&mut self.merged]) {
+                state.time_format = time_format;
+            }
+            return true;
+        }
+        if !self.is_multi_stream() {
+            return false;
+        }
+
+        match action {
+            KeyAction::NextStream => self.focused = (self.focused + 1) % self.streams.len(),
+            KeyAction::PrevStream => {
+                self.focused = (self.focused + self.streams.len() - 1) % self.streams.len()
+            }
+            KeyAction::CycleLayout => self.layout = self.layout.next(),
+            _ => return false,
+        }
+        self.sync_merged_poll();
+
+        true
+    }
+
+    fn on_stream_event(&mut self, stream: StreamId, event: StreamEvent) {
         match event {
-            AppEvent::Chat(mut msg) => {
-                if self.graphics.kitty_supported {
-                    if let Some(avatar) = msg.avatar.as_ref() {
-                        let _ = prepare_kitty_avatar(avatar, &mut self.graphics.loaded_avatar_ids);
+            StreamEvent::Chat(mut msg) => {
+                if !self.graphics.enabled() {
+                    msg.avatar = None;
+                } else if let Some(avatar) = &msg.avatar {
+                    self.graphics.register(avatar);
+                } else {
+                    // sent while its avatar was downloading, but queued behind `AvatarReady`
+                    msg.avatar = self.late_avatars.get(&msg.author_channel_id).cloned();
+                }
+                self.update_stream(stream, |state| state.push_message((*msg).clone()))
+            }
+            StreamEvent::Moderation(event) => self.update_stream(stream, |state| {
+                state.apply_moderation(stream, event.clone())
+            }),
+            StreamEvent::Poll(poll) => {
+                self.streams[stream].update_poll(poll);
+                if stream == self.focused {
+                    self.sync_merged_poll();
+                }
+            }
+            StreamEvent::Status(status) => {
+                self.streams[stream].update_status(status);
+                self.merged.update_status(self.merged_status());
+            }
+            StreamEvent::Error(error) => {
+                let merged_error = if self.is_multi_stream() {
+                    AppError {
+                        category: error.category,
+                        message: format!("{}: {}", self.streams[stream].title, error.message),
                     }
                 } else {
-                    msg.avatar = None;
+                    error.clone()
+                };
+                self.streams[stream].push_error(error);
+                self.merged.push_error(merged_error);
+            }
+            StreamEvent::Outgoing(result) => {
+                self.update_stream(stream, |state| state.apply_outgoing(result.clone()))
+            }
+            StreamEvent::StatsUpdate(stats) => {
+                self.streams[stream].update_stats(stats.viewer_count);
+                let total = self.streams.iter().map(|s| s.stats.viewer_count).sum();
+                self.merged.update_stats(total);
+            }
+        }
+    }
+
+    /// The merged view shows the focused stream's poll, it only has room for one.
+    fn sync_merged_poll(&mut self) {
+        match self.streams[self.focused].poll.clone() {
+            Some(poll) => self.merged.update_poll(poll),
+            None => self.merged.poll = None,
+        }
+    }
+
+    /// Live while any stream is, disconnected only once every stream is.
+    fn merged_status(&self) -> StatusEvent {
+        let statuses = || self.streams.iter().map(|s| &s.status);
+        if statuses().any(|s| matches!(s, StatusEvent::Connected)) {
+            StatusEvent::Connected
+        } else if statuses().all(|s| matches!(s, StatusEvent::Disconnected(_))) {
+            statuses()
+                .find(|s| matches!(s, StatusEvent::Disconnected(DisconnectReason::Error(_))))
+                .unwrap_or(&self.streams[0].status)
+                .clone()
+        } else {
+            StatusEvent::Connecting
+        }
+    }
+
+    pub fn on_event(&mut self, event: AppEvent) -> bool {
+        match event {
+            AppEvent::Stream(stream, event) => self.on_stream_event(stream, event),
+            AppEvent::Tick => self.release_avatars(),
+            AppEvent::AvatarReady { channel_id, avatar } if self.graphics.enabled() => {
+                self.graphics.register(&avatar);
+                for state in self.streams.iter_mut().chain([&mut self.merged]) {
+                    state.set_avatar(&channel_id, &avatar);
                 }
-                self.state.push_message(msg)
+                self.late_avatars.insert(channel_id, avatar);
             }
-            AppEvent::Input(key) => {
-                if self.state.handle_key(key) {
-                    return true;
+            AppEvent::History {
+                view,
+                before_id,
+                messages,
+            } => {
+                let state = self.view_mut(view);
+                state.history_loading = false;
+                match messages {
+                    // trimmed in the meantime, the page would leave a gap
+                    Ok(_) if state.oldest_message_id() != Some(before_id.as_str()) => {}
+                    Ok(older) => state.prepend_history(older),
+                    Err(e) => state.push_error(e),
                 }
             }
-            AppEvent::StatsUpdate(stats) => self.state.update_stats(stats.viewer_count),
-            _ => {
-                // todo
+            AppEvent::AuthorHistory {
+                view,
+                channel_id,
+                archived,
+            } => {
+                let state = self.view_mut(view);
+                let archived = archived.map_err(|e| state.push_error(e)).ok();
+                state.open_author(&channel_id, archived);
             }
+            AppEvent::Input(key) => {
+                if self.handle_app_key(key) {
+                    return false;
+                }
+                let view = self.active_view();
+                if let Some(action) = self.view_mut(view).handle_key(key) {
+                    return self.on_action(action);
+                }
+            }
+            // no avatars are drawn
+            AppEvent::AvatarReady { .. } => {}
         }
 
         false
     }
 
+    /// Screen areas of the tab bar and of every visible view.
+    fn view_areas(&self, area: Rect) -> (Option<Rect>, Vec<(View, Rect)>) {
+        if !self.is_multi_stream() {
+            return (None, vec![(View::Stream(0), area)]);
+        }
+
+        if self.layout == StreamLayout::Tiled {
+            let panes = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints(vec![
+                    Constraint::Ratio(1, self.streams.len() as u32);
+                    self.streams.len()
+                ])
+                .split(area);
+            let views = panes
+                .iter()
+                .enumerate()
+                .map(|(i, pane)| (View::Stream(i), *pane))
+                .collect();
+            return (None, views);
+        }
+
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(1), Constraint::Min(1)])
+            .split(area);
+        (Some(rows[0]), vec![(self.active_view(), rows[1])])
+    }
+
     async fn handle_tui(
         &mut self,
         terminal: &mut Terminal<CrosstermBackend<Stdout>>,
     ) -> anyhow::Result<()> {
-        terminal.draw(|f| draw(f, &self.state))?;
         let size = terminal.size()?;
-        let visible_rows = size.height.saturating_sub(3) as usize;
-        let chat_width = size.width.saturating_sub(2) as usize;
-        let max_scroll = max_scroll_for_viewport(&self.state, chat_width, visible_rows);
-        self.state.update_scroll_state(visible_rows, max_scroll);
+        let (tabs_area, views) = self.view_areas(Rect::new(0, 0, size.width, size.height));
+        for (view, area) in &views {
+            update_view_scroll(self.view_mut(*view), *area);
+        }
+
+        let titles: Vec<&str> = self.streams.iter().map(|s| s.title.as_str()).collect();
+        let multi_view = views.len() > 1;
+        let (streams, merged, graphics) = (&self.streams, &self.merged, &mut self.graphics);
+        terminal.draw(|f| {
+            if let Some(area) = tabs_area {
+                draw_tabs(f, area, &titles, self.focused, merged);
+            }
+            for (view, area) in &views {
+                let focused =
+                    !multi_view || matches!(view, View::Stream(id) if *id == self.focused);
+                draw(f, *area, view_state(streams, merged, *view), focused);
+            }
+            graphics.paint(f.buffer_mut());
+        })?;
+        self.graphics.flush_images()?;
 
         Ok(())
     }
@@ -91,7 +523,7 @@ impl App {
     ) -> anyhow::Result<()> {
         self.handle_tui(terminal).await?;
 
-        loop {
+        'events: loop {
             let Some(ev) = rx.recv().await else { break };
             if self.on_event(ev) {
                 break;
@@ -99,7 +531,7 @@ impl App {
 
             while let Ok(ev) = rx.try_recv() {
                 if self.on_event(ev) {
-                    return Ok(());
+                    break 'events;
                 }
             }
 
@@ -109,24 +541,68 @@ impl App {
     }
 }
 
-fn prepare_kitty_avatar(
-    avatar: &KittyAvatar,
-    loaded_avatar_ids: &mut HashSet<u32>,
-) -> anyhow::Result<()> {
-    let mut out = stdout();
-
-    if loaded_avatar_ids.insert(avatar.id) {
-        write!(
-            out,
-            "\x1b_Ga=T,U=1,t=t,f=32,s={},v={},i={},c={},r=1,q=2;{}\x1b\\",
-            avatar.width,
-            avatar.height,
-            avatar.id,
-            avatar.cols,
-            STANDARD.encode(avatar.path.as_bytes()),
-        )?;
-    }
-
-    out.flush()?;
-    Ok(())
+/// Archived messages of `watched` older than `before_id` that can be shown. Pages of only
+/// bans, deletions and the like are skipped, so an empty page means the archive has no more.
+fn history_page(
+    archive: &Archive,
+    video_ids: &[String],
+    watched: &[&str],
+    before_id: &str,
+) -> anyhow::Result<Vec<ChatMessage>> {
+    let mut before_id = before_id.to_string();
+    loop {
+        let archived = archive.older_than(watched, &before_id, HISTORY_PAGE_SIZE)?;
+        let Some(oldest) = archived.first().and_then(|a| a.item.id.clone()) else {
+            return Ok(Vec::new());
+        };
+        let messages: Vec<ChatMessage> = archived
+            .iter()
+            .filter_map(|a| {
+                let stream = video_ids.iter().position(|id| *id == a.video_id)?;
+                chat_message(&a.item, stream)
+            })
+            .collect();
+        if !messages.is_empty() {
+            return Ok(messages);
+        }
+        before_id = oldest;
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn view_state<'a>(streams: &'a [AppState], merged: &'a AppState, view: View) -> &'a AppState {
+    match view {
+        View::Stream(id) => &streams[id],
+        View::Merged => merged,
+    }
+}
+
+fn update_view_scroll(state: &mut AppState, area: Rect) {
+    let author_max_scroll = author_max_scroll(area, state);
+    if let Some(details) = &mut state.author {
+        details.scroll_offset = details.scroll_offset.min(author_max_scroll);
+    }
+
+    let chat_area = layout(area, state).chat;
+    let visible_rows = chat_area.height.saturating_sub(2) as usize;
+    let chat_width = chat_area.width.saturating_sub(2) as usize;
+    let max_scroll = max_scroll_for_viewport(state, chat_width, visible_rows);
+    state.update_scroll_state(visible_rows, max_scroll);
+    // the selection stays in view, a search match is only scrolled to when jumped to
+    let reveal = state.search.as_mut().filter(|s| s.reveal).and_then(|s| {
+        s.reveal = false;
+        s.current
+    });
+    let Some(index) = state.selected.or(reveal) else {
+        return;
+    };
+    if let Some(range) = message_scroll_range(state, index, chat_width, visible_rows) {
+        state.scroll_state.scroll_offset = state
+            .scroll_state
+            .scroll_offset
+            .clamp(*range.start(), *range.end());
+    }
 }
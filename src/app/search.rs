@@ -0,0 +1,134 @@
+use regex::{Regex, RegexBuilder};
+
+/// A `/` search over the message text, matches are highlighted and n/N jump between them.
+pub struct Search {
+    pub query: String,
+    /// Treat `query` as a regex instead of plain text, Tab toggles it while typing.
+    pub regex_mode: bool,
+    /// Still typing the query, keys go to the search bar.
+    pub editing: bool,
+    /// `None` while the query is empty or not a valid regex.
+    pattern: Option<Regex>,
+    /// Index into `messages` of the match last jumped to.
+    pub current: Option<usize>,
+    /// Set by a jump, the next draw scrolls `current` into view once.
+    pub reveal: bool,
+}
+
+impl Search {
+    pub fn new() -> Self {
+        Self {
+            query: String::new(),
+            regex_mode: false,
+            editing: true,
+            pattern: None,
+            current: None,
+            reveal: false,
+        }
+    }
+
+    /// Recompiles the pattern after `query` or `regex_mode` changed. Matching ignores
+    /// case unless the query contains an uppercase letter.
+    pub fn update_pattern(&mut self) {
+        let source = if self.regex_mode {
+            self.query.clone()
+        } else {
+            regex::escape(&self.query)
+        };
+        self.pattern = (!self.query.is_empty())
+            .then(|| {
+                RegexBuilder::new(&source)
+                    .case_insensitive(!self.query.chars().any(char::is_uppercase))
+                    .build()
+                    .ok()
+            })
+            .flatten();
+    }
+
+    /// Whether the query is a regex that doesn't compile.
+    pub fn is_invalid(&self) -> bool {
+        !self.query.is_empty() && self.pattern.is_none()
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.ranges(text).next().is_some()
+    }
+
+    /// Byte ranges of the non-empty matches in `text`.
+    pub fn ranges<'a>(&'a self, text: &'a str) -> impl Iterator<Item = (usize, usize)> + 'a {
+        self.pattern
+            .iter()
+            .flat_map(move |pattern| pattern.find_iter(text))
+            .filter(|m| !m.is_empty())
+            .map(|m| (m.start(), m.end()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compiled(query: &str, regex_mode: bool) -> Search {
+        let mut search = Search::new();
+        search.query = query.to_string();
+        search.regex_mode = regex_mode;
+        search.update_pattern();
+        search
+    }
+
+    fn ranges(search: &Search, text: &str) -> Vec<(usize, usize)> {
+        search.ranges(text).collect()
+    }
+
+    #[test]
+    fn lowercase_queries_ignore_case() {
+        let search = compiled("gg", false);
+        assert_eq!(ranges(&search, "GG gg Gg"), vec![(0, 2), (3, 5), (6, 8)]);
+
+        let search = compiled("GG", false);
+        assert_eq!(ranges(&search, "GG gg Gg"), vec![(0, 2)]);
+        assert!(!search.is_match("gg"));
+    }
+
+    #[test]
+    fn plain_queries_match_regex_characters_literally() {
+        let search = compiled("a.b", false);
+        assert_eq!(ranges(&search, "axb a.b"), vec![(4, 7)]);
+
+        let search = compiled("a.b", true);
+        assert_eq!(ranges(&search, "axb a.b"), vec![(0, 3), (4, 7)]);
+    }
+
+    #[test]
+    fn invalid_regexes_match_nothing() {
+        let search = compiled("(unclosed", true);
+        assert!(search.is_invalid());
+        assert!(!search.is_match("(unclosed"));
+
+        // the same text is fine as a plain query
+        assert!(!compiled("(unclosed", false).is_invalid());
+        assert!(!compiled("", true).is_invalid());
+        assert!(!compiled("", true).is_match("anything"));
+    }
+
+    #[test]
+    fn empty_regex_matches_are_skipped() {
+        let search = compiled("x*", true);
+        assert_eq!(ranges(&search, "axxb"), vec![(1, 3)]);
+    }
+
+    #[test]
+    fn ranges_are_byte_offsets_in_multibyte_text() {
+        let text = "héllo wörld ÉCOLE";
+        let search = compiled("é", false);
+        let found = ranges(&search, text);
+        assert_eq!(found, vec![(1, 3), (14, 16)]);
+        for (start, end) in found {
+            assert!(text.is_char_boundary(start) && text.is_char_boundary(end));
+            assert_eq!(text[start..end].to_lowercase(), "é");
+        }
+
+        let search = compiled("w.rld", true);
+        assert_eq!(ranges(&search, text), vec![(7, 13)]);
+    }
+}
@@ -1,6 +1,19 @@
-use crate::app::event::ChatMessage;
+use crate::app::author::{ArchivedAuthor, AuthorDetails, AuthorSeen};
+use crate::app::event::{
+    AppError, Avatar, ChatMessage, Command, Delivery, ErrorCategory, MessageKind, ModerationEvent,
+    OutgoingResult, Poll, Removal, StatusEvent, StreamId,
+};
+use crate::app::filter::Filter;
+use crate::app::keymap::{KeyAction, KeyMap, KeyMode};
+use crate::app::search::Search;
+use crate::app::theme::{Badges, Theme};
+use crate::app::time::TimeFormat;
+use crate::config::Config;
+use chrono::{DateTime, Utc};
 use ratatui::crossterm::event::{KeyCode, KeyEvent};
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::Instant;
 
 pub struct ScrollState {
     pub scroll_offset: usize,
@@ -9,22 +22,178 @@ pub struct ScrollState {
     pub max_scroll_rows: usize,
 }
 
+pub struct Notification {
+    pub error: AppError,
+    pub received_at: Instant,
+    pub timestamp: DateTime<Utc>,
+}
+
+pub struct ErrorLog {
+    pub visible: bool,
+    pub scroll_offset: usize,
+}
+
+/// What the app should do in response to a key press.
+pub enum Action {
+    Quit,
+    SendMessage(String),
+    Moderate {
+        stream: StreamId,
+        command: Command,
+    },
+    /// Scrolled past the oldest message, fetch older ones from the archive.
+    LoadHistory,
+    /// Open the author popup, with the author's archived messages if there is an archive.
+    ShowAuthor {
+        channel_id: String,
+    },
+}
+
+/// Modal prompts shown over the chat while moderating.
+pub enum Dialog {
+    Confirm {
+        stream: StreamId,
+        prompt: String,
+        command: Command,
+    },
+    TimeoutDuration {
+        stream: StreamId,
+        author: String,
+        channel_id: String,
+        input: String,
+    },
+}
+
 pub struct Stats {
-    pub viewer_count: u32
+    pub viewer_count: u32,
 }
 pub struct AppState {
     pub title: String,
     pub messages: VecDeque<ChatMessage>,
-    // todo: pub status: String,
+    pub status: StatusEvent,
     pub scroll_state: ScrollState,
-    pub stats: Stats
+    pub stats: Stats,
+    pub poll: Option<Poll>,
+    pub poll_collapsed: bool,
+    pub notifications: VecDeque<Notification>,
+    pub error_log: ErrorLog,
+    pub write_enabled: bool,
+    /// Message being composed, `None` while the input box is closed.
+    pub input: Option<String>,
+    /// Index into `messages` of the selection cursor, `None` outside selection mode.
+    pub selected: Option<usize>,
+    pub dialog: Option<Dialog>,
+    /// The author popup, `None` while closed.
+    pub author: Option<AuthorDetails>,
+    pub search: Option<Search>,
+    pub filter: Filter,
+    /// Prefix every message with its stream color, set for the merged view.
+    pub show_stream_badges: bool,
+    /// Set once the archive has nothing older than `messages`.
+    pub history_exhausted: bool,
+    /// A page of history was asked for and hasn't arrived yet.
+    pub history_loading: bool,
+    /// Messages paged in from the archive at the front, the cap while scrolled back
+    /// leaves them alone.
+    history_len: usize,
+    pub time_format: TimeFormat,
+    pub theme: Arc<Theme>,
+    pub badges: Arc<Badges>,
+    pub keymap: Arc<KeyMap>,
+    max_messages: usize,
+    /// Live messages per author channel id, history paged in from the archive isn't counted.
+    seen_authors: HashMap<String, AuthorSeen>,
 }
 
-const MAX_MESSAGES: usize = 500;
+/// YouTube rejects chat messages longer than this.
+pub const MAX_INPUT_CHARS: usize = 200;
+const MAX_NOTIFICATIONS: usize = 200;
+/// Longest timeout YouTube accepts, 24 hours.
+const MAX_TIMEOUT_SECS: u64 = 86_400;
+/// Id prefix of local echoes until YouTube assigns the real id.
+pub const LOCAL_ID_PREFIX: &str = "local-";
 
 impl AppState {
-    pub fn push_message(&mut self, msg: ChatMessage) {
-        if !self.scroll_state.auto_scroll {
+    pub fn new(
+        title: String,
+        write_enabled: bool,
+        time_format: TimeFormat,
+        config: &Config,
+    ) -> Self {
+        Self {
+            title,
+            messages: Default::default(),
+            status: StatusEvent::Connecting,
+            scroll_state: ScrollState {
+                scroll_offset: 0,
+                auto_scroll: true,
+                visible_rows: 1,
+                max_scroll_rows: 0,
+            },
+            stats: Stats { viewer_count: 0 },
+            poll: None,
+            poll_collapsed: false,
+            notifications: Default::default(),
+            error_log: ErrorLog {
+                visible: false,
+                scroll_offset: 0,
+            },
+            write_enabled,
+            input: None,
+            selected: None,
+            dialog: None,
+            author: None,
+            search: None,
+            filter: Filter::All,
+            show_stream_badges: false,
+            history_exhausted: false,
+            history_loading: false,
+            history_len: 0,
+            time_format,
+            theme: config.theme.clone(),
+            badges: config.badges.clone(),
+            keymap: config.keymap.clone(),
+            max_messages: config.max_messages,
+            seen_authors: HashMap::new(),
+        }
+    }
+
+    pub fn push_message(&mut self, mut msg: ChatMessage) {
+        if let MessageKind::GiftMembershipReceived {
+            gifting_message_id,
+            gifter,
+            ..
+        } = &mut msg.kind
+        {
+            *gifter = self
+                .messages
+                .iter()
+                .rev()
+                .find(|m| &m.id == gifting_message_id)
+                .map(|m| m.author.clone());
+        }
+
+        // our own messages come back from the stream, replace the local echo
+        if !msg.id.is_empty()
+            && let Some(existing) = self.messages.iter_mut().rev().find(|m| m.id == msg.id)
+        {
+            *existing = msg;
+            return;
+        }
+
+        if !msg.author_channel_id.is_empty() {
+            let seen = self
+                .seen_authors
+                .entry(msg.author_channel_id.clone())
+                .or_insert(AuthorSeen {
+                    first_seen: msg.timestamp,
+                    count: 0,
+                });
+            seen.first_seen = seen.first_seen.min(msg.timestamp);
+            seen.count += 1;
+        }
+
+        if !self.scroll_state.auto_scroll && self.filter.matches(&msg) {
             self.scroll_state.scroll_offset = self.scroll_state.scroll_offset.saturating_add(1);
         }
         self.messages.push_back(msg);
@@ -32,11 +201,219 @@ impl AppState {
             self.scroll_state.scroll_offset = 0;
         }
 
-        while self.messages.len() > MAX_MESSAGES {
-            self.messages.pop_front();
-            if self.scroll_state.scroll_offset > 0 {
-                self.scroll_state.scroll_offset = self.scroll_state.scroll_offset.saturating_sub(1);
+        // keep paged-in history while scrolled back, with a hard cap on live messages,
+        // and trim once following again
+        let cap = if self.scroll_state.auto_scroll {
+            self.max_messages
+        } else {
+            2 * self.max_messages + self.history_len
+        };
+        while self.messages.len() > cap {
+            let oldest = if self.scroll_state.auto_scroll {
+                self.history_len = self.history_len.saturating_sub(1);
+                0
+            } else {
+                // the oldest live message, right after the history
+                self.history_len
+            };
+            self.remove_message(oldest);
+        }
+    }
+
+    /// Id of the oldest message that came from YouTube, the anchor for paging back.
+    pub fn oldest_message_id(&self) -> Option<&str> {
+        self.messages
+            .iter()
+            .map(|m| m.id.as_str())
+            .find(|id| !id.is_empty() && !id.starts_with(LOCAL_ID_PREFIX))
+    }
+
+    /// Inserts archived messages, oldest first, in front of the buffer.
+    pub fn prepend_history(&mut self, older: Vec<ChatMessage>) {
+        if older.is_empty() {
+            self.history_exhausted = true;
+            return;
+        }
+
+        self.selected = self.selected.map(|i| i + older.len());
+        if let Some(search) = &mut self.search {
+            search.current = search.current.map(|i| i + older.len());
+        }
+        self.history_len += older.len();
+        for msg in older.into_iter().rev() {
+            self.messages.push_front(msg);
+        }
+    }
+
+    pub fn apply_moderation(&mut self, stream: StreamId, event: ModerationEvent) {
+        match event {
+            ModerationEvent::MessageDeleted { message_id } => {
+                self.mark_removed(|m| m.id == message_id, Removal::Deleted)
+            }
+            ModerationEvent::MessageRetracted { message_id } => {
+                self.mark_removed(|m| m.id == message_id, Removal::Retracted)
+            }
+            ModerationEvent::UserBanned {
+                channel_id,
+                display_name,
+                duration_secs,
+                timestamp,
+            } => {
+                // a ban only applies to the chat it was issued in
+                self.mark_removed(
+                    |m| m.stream == stream && m.author_channel_id == channel_id,
+                    Removal::AuthorBanned,
+                );
+                let message = match duration_secs {
+                    Some(secs) => format!(
+                        "{} was timed out for {}",
+                        display_name,
+                        format_duration(secs)
+                    ),
+                    None => format!("{} was banned permanently", display_name),
+                };
+                self.push_message(ChatMessage {
+                    id: String::new(),
+                    stream,
+                    timestamp,
+                    author: String::new(),
+                    author_channel_id: String::new(),
+                    message,
+                    kind: MessageKind::System,
+                    avatar: None,
+                    is_member: false,
+                    is_moderator: false,
+                    is_owner: false,
+                    is_verified: false,
+                    member_level: None,
+                    removed: None,
+                    delivery: None,
+                });
+            }
+        }
+    }
+
+    pub fn update_status(&mut self, status: StatusEvent) {
+        self.status = status;
+    }
+
+    pub fn push_error(&mut self, error: AppError) {
+        self.notifications.push_back(Notification {
+            error,
+            received_at: Instant::now(),
+            timestamp: Utc::now(),
+        });
+        while self.notifications.len() > MAX_NOTIFICATIONS {
+            self.notifications.pop_front();
+        }
+    }
+
+    pub fn push_local_echo(&mut self, stream: StreamId, local_id: String, text: String) {
+        self.push_message(ChatMessage {
+            id: local_id,
+            stream,
+            timestamp: Utc::now(),
+            author: "You".to_string(),
+            author_channel_id: String::new(),
+            message: text,
+            kind: MessageKind::Text,
+            avatar: None,
+            is_member: false,
+            is_moderator: false,
+            is_owner: false,
+            is_verified: false,
+            member_level: None,
+            removed: None,
+            delivery: Some(Delivery::Pending),
+        });
+    }
+
+    pub fn apply_outgoing(&mut self, result: OutgoingResult) {
+        match result {
+            OutgoingResult::Sent {
+                local_id,
+                message_id,
+            } => {
+                let Some(index) = self.messages.iter().position(|m| m.id == local_id) else {
+                    return;
+                };
+                // the stream can deliver the message before the send returns
+                if self.messages.iter().any(|m| m.id == message_id) {
+                    let echo = self.remove_message(index);
+                    // the echo was the newest row, below the view when scrolled back
+                    if !self.scroll_state.auto_scroll
+                        && echo.is_some_and(|echo| self.filter.matches(&echo))
+                    {
+                        self.scroll_state.scroll_offset =
+                            self.scroll_state.scroll_offset.saturating_sub(1);
+                    }
+                    return;
+                }
+                let m = &mut self.messages[index];
+                m.id = message_id;
+                m.delivery = None;
             }
+            OutgoingResult::Failed { local_id, error } => {
+                if let Some(m) = self.messages.iter_mut().find(|m| m.id == local_id) {
+                    m.delivery = Some(Delivery::Failed);
+                }
+                self.push_error(error);
+            }
+        }
+    }
+
+    /// Removes the message at `index`, the selection and the current match follow it.
+    fn remove_message(&mut self, index: usize) -> Option<ChatMessage> {
+        let msg = self.messages.remove(index)?;
+        let shift = |i: usize| match i.cmp(&index) {
+            std::cmp::Ordering::Less => Some(i),
+            std::cmp::Ordering::Equal => None,
+            std::cmp::Ordering::Greater => Some(i - 1),
+        };
+        self.selected = self.selected.and_then(shift);
+        if let Some(search) = &mut self.search {
+            search.current = search.current.and_then(shift);
+        }
+        Some(msg)
+    }
+
+    pub fn update_poll(&mut self, poll: Poll) {
+        if self.poll.as_ref().is_none_or(|p| p.id != poll.id) {
+            self.poll_collapsed = false;
+        }
+        self.poll = Some(poll);
+    }
+
+    /// Ids of the avatars on messages in this view, including the author popup.
+    pub fn avatar_ids(&self) -> impl Iterator<Item = u32> + '_ {
+        let details = self
+            .author
+            .iter()
+            .flat_map(|details| std::iter::once(&details.author).chain(&details.messages));
+        self.messages
+            .iter()
+            .chain(details)
+            .filter_map(|m| m.avatar.as_ref().map(|a| a.id))
+    }
+
+    /// Gives `avatar` to the author's messages that went out before it was downloaded.
+    pub fn set_avatar(&mut self, channel_id: &str, avatar: &Arc<Avatar>) {
+        let details = self.author.as_mut().into_iter().flat_map(|details| {
+            std::iter::once(&mut details.author).chain(details.messages.iter_mut())
+        });
+        for m in self
+            .messages
+            .iter_mut()
+            .chain(details)
+            .filter(|m| m.author_channel_id == channel_id && m.avatar.is_none())
+        {
+            m.avatar = Some(avatar.clone());
+        }
+    }
+
+    fn mark_removed(&mut self, matches: impl Fn(&ChatMessage) -> bool, removal: Removal) {
+        for m in self.messages.iter_mut().filter(|m| matches(m)) {
+            m.removed.get_or_insert(removal);
         }
     }
 
@@ -61,35 +438,409 @@ impl AppState {
         self.stats.viewer_count = viewer_count;
     }
 
-    pub fn handle_key(&mut self, key: KeyEvent) -> bool {
-        let page = self.scroll_state.visible_rows.max(1);
+    fn handle_error_log_key(&mut self, key: KeyEvent) {
+        let log = &mut self.error_log;
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('e') => log.visible = false,
+            KeyCode::Up => log.scroll_offset = log.scroll_offset.saturating_add(1),
+            KeyCode::Down => log.scroll_offset = log.scroll_offset.saturating_sub(1),
+            KeyCode::PageUp => log.scroll_offset = log.scroll_offset.saturating_add(10),
+            KeyCode::PageDown => log.scroll_offset = log.scroll_offset.saturating_sub(10),
+            _ => {}
+        }
+        log.scroll_offset = log
+            .scroll_offset
+            .min(self.notifications.len().saturating_sub(1));
+    }
+
+    fn handle_input_key(&mut self, key: KeyEvent) -> Option<Action> {
+        let input = self.input.as_mut()?;
         match key.code {
-            KeyCode::Esc | KeyCode::Char('q') => return true,
-            KeyCode::Up => {
+            KeyCode::Esc => self.input = None,
+            KeyCode::Enter => {
+                let text = self.input.take().unwrap_or_default();
+                let text = text.trim();
+                if !text.is_empty() {
+                    return Some(Action::SendMessage(text.to_string()));
+                }
+            }
+            KeyCode::Backspace => {
+                input.pop();
+            }
+            KeyCode::Char(c) if input.chars().count() < MAX_INPUT_CHARS => input.push(c),
+            _ => {}
+        }
+
+        None
+    }
+
+    fn require_write(&mut self) -> bool {
+        if !self.write_enabled {
+            self.push_error(AppError {
+                category: ErrorCategory::Other,
+                message: "write actions are disabled, restart with --write".to_string(),
+            });
+        }
+        self.write_enabled
+    }
+
+    fn open_input(&mut self) {
+        if self.require_write() {
+            self.input = Some(String::new());
+        }
+    }
+
+    fn is_selectable(m: &ChatMessage) -> bool {
+        !m.author_channel_id.is_empty() && m.delivery.is_none()
+    }
+
+    fn can_select(&self, i: usize) -> bool {
+        let m = &self.messages[i];
+        Self::is_selectable(m) && self.filter.matches(m)
+    }
+
+    fn move_selection(&mut self, up: bool) {
+        let Some(current) = self.selected else {
+            return;
+        };
+        let next = if up {
+            (0..current).rev().find(|&i| self.can_select(i))
+        } else {
+            (current + 1..self.messages.len()).find(|&i| self.can_select(i))
+        };
+        if let Some(next) = next {
+            self.selected = Some(next);
+        }
+    }
+
+    fn start_selection(&mut self) {
+        self.selected = (0..self.messages.len()).rev().find(|&i| self.can_select(i));
+        if self.selected.is_some() {
+            self.scroll_state.auto_scroll = false;
+        }
+    }
+
+    fn open_moderation_dialog(&mut self, action: KeyAction) {
+        let Some(m) = self.selected.and_then(|i| self.messages.get(i)) else {
+            return;
+        };
+        let (stream, author, channel_id, message_id) = (
+            m.stream,
+            m.author.clone(),
+            m.author_channel_id.clone(),
+            m.id.clone(),
+        );
+        if !self.require_write() {
+            return;
+        }
+
+        self.dialog = match action {
+            KeyAction::Delete => Some(Dialog::Confirm {
+                stream,
+                prompt: format!("Delete this message from {}?", author),
+                command: Command::DeleteMessage { message_id },
+            }),
+            KeyAction::Timeout => Some(Dialog::TimeoutDuration {
+                stream,
+                author,
+                channel_id,
+                input: "5m".to_string(),
+            }),
+            KeyAction::Ban => Some(Dialog::Confirm {
+                stream,
+                prompt: format!("Ban {} permanently?", author),
+                command: Command::BanUser {
+                    channel_id,
+                    duration_secs: None,
+                },
+            }),
+            _ => None,
+        };
+    }
+
+    fn handle_dialog_key(&mut self, key: KeyEvent) -> Option<Action> {
+        match self.dialog.take()? {
+            Dialog::Confirm {
+                stream,
+                prompt,
+                command,
+            } => match key.code {
+                KeyCode::Char('y') | KeyCode::Enter => {
+                    return Some(Action::Moderate { stream, command });
+                }
+                KeyCode::Char('n') | KeyCode::Esc => {}
+                _ => {
+                    self.dialog = Some(Dialog::Confirm {
+                        stream,
+                        prompt,
+                        command,
+                    })
+                }
+            },
+            Dialog::TimeoutDuration {
+                stream,
+                author,
+                channel_id,
+                mut input,
+            } => match key.code {
+                KeyCode::Esc => {}
+                KeyCode::Enter => match parse_duration(&input) {
+                    Some(secs) => {
+                        self.dialog = Some(Dialog::Confirm {
+                            stream,
+                            prompt: format!("Time out {} for {}?", author, format_duration(secs)),
+                            command: Command::BanUser {
+                                channel_id,
+                                duration_secs: Some(secs),
+                            },
+                        })
+                    }
+                    None => {
+                        self.dialog = Some(Dialog::TimeoutDuration {
+                            stream,
+                            author,
+                            channel_id,
+                            input,
+                        })
+                    }
+                },
+                code => {
+                    match code {
+                        KeyCode::Backspace => {
+                            input.pop();
+                        }
+                        KeyCode::Char(c) if c.is_ascii_alphanumeric() => input.push(c),
+                        _ => {}
+                    }
+                    self.dialog = Some(Dialog::TimeoutDuration {
+                        stream,
+                        author,
+                        channel_id,
+                        input,
+                    });
+                }
+            },
+        }
+
+        None
+    }
+
+    /// Switches the view to `filter` and follows the chat again, the rows above changed.
+    fn set_filter(&mut self, filter: Filter) {
+        self.filter = filter;
+        self.selected = None;
+        if let Some(search) = &mut self.search {
+            search.current = None;
+        }
+        self.scroll_state.scroll_offset = 0;
+        self.scroll_state.auto_scroll = true;
+    }
+
+    /// Opens the author popup for `channel_id`, who must have a message in the view.
+    pub fn open_author(&mut self, channel_id: &str, archived: Option<ArchivedAuthor>) {
+        let session: Vec<ChatMessage> = self
+            .messages
+            .iter()
+            .filter(|m| m.author_channel_id == channel_id)
+            .cloned()
+            .collect();
+        let Some(author) = session.last().cloned() else {
+            return;
+        };
+        let seen = self.seen_authors.get(channel_id).copied();
+        self.author = Some(AuthorDetails::new(author, seen, session, archived));
+    }
+
+    fn handle_author_key(&mut self, key: KeyEvent) {
+        let Some(details) = self.author.as_mut() else {
+            return;
+        };
+        let offset = &mut details.scroll_offset;
+        match key.code {
+            KeyCode::Esc | KeyCode::Enter | KeyCode::Char('q') => self.author = None,
+            KeyCode::Up => *offset = offset.saturating_add(1),
+            KeyCode::Down => *offset = offset.saturating_sub(1),
+            KeyCode::PageUp => *offset = offset.saturating_add(10),
+            KeyCode::PageDown => *offset = offset.saturating_sub(10),
+            KeyCode::Home => *offset = usize::MAX,
+            KeyCode::End => *offset = 0,
+            _ => {}
+        }
+    }
+
+    fn handle_selection_key(&mut self, key: KeyEvent) -> Option<Action> {
+        if key.code == KeyCode::Esc {
+            self.selected = None;
+            return None;
+        }
+
+        match self.keymap.action(KeyMode::Selection, key.code)? {
+            KeyAction::Select => self.selected = None,
+            KeyAction::ShowAuthor => {
+                let m = self.selected.and_then(|i| self.messages.get(i))?;
+                return Some(Action::ShowAuthor {
+                    channel_id: m.author_channel_id.clone(),
+                });
+            }
+            KeyAction::FilterAuthor => {
+                if let Some(m) = self.selected.and_then(|i| self.messages.get(i)) {
+                    let filter = Filter::Author {
+                        channel_id: m.author_channel_id.clone(),
+                        name: m.author.clone(),
+                    };
+                    self.set_filter(filter);
+                }
+            }
+            KeyAction::ScrollUp => self.move_selection(true),
+            KeyAction::ScrollDown => self.move_selection(false),
+            action @ (KeyAction::Delete | KeyAction::Timeout | KeyAction::Ban) => {
+                self.open_moderation_dialog(action)
+            }
+            _ => {}
+        }
+
+        None
+    }
+
+    /// Indexes into `messages` of the messages matching the search, oldest first.
+    pub fn search_matches(&self) -> Vec<usize> {
+        let Some(search) = &self.search else {
+            return Vec::new();
+        };
+        self.messages
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| self.filter.matches(m) && search.is_match(&m.message))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Moves to the next older or newer match, wrapping around at either end.
+    fn jump_to_match(&mut self, older: bool) {
+        let matches = self.search_matches();
+        let Some(search) = self.search.as_mut() else {
+            return;
+        };
+        let next = match (search.current, older) {
+            (Some(current), true) => matches
+                .iter()
+                .rev()
+                .find(|&&i| i < current)
+                .or(matches.last()),
+            (Some(current), false) => matches.iter().find(|&&i| i > current).or(matches.first()),
+            (None, _) => matches.last(),
+        };
+        if let Some(&next) = next {
+            search.current = Some(next);
+            search.reveal = true;
+            self.scroll_state.auto_scroll = false;
+        }
+    }
+
+    fn handle_search_key(&mut self, key: KeyEvent) {
+        let Some(search) = self.search.as_mut() else {
+            return;
+        };
+        match key.code {
+            KeyCode::Esc => self.search = None,
+            KeyCode::Enter if search.query.is_empty() => self.search = None,
+            KeyCode::Enter => {
+                search.editing = false;
+                self.jump_to_match(true);
+            }
+            KeyCode::Tab => {
+                search.regex_mode = !search.regex_mode;
+                search.update_pattern();
+            }
+            KeyCode::Backspace => {
+                search.query.pop();
+                search.update_pattern();
+            }
+            KeyCode::Char(c) => {
+                search.query.push(c);
+                search.update_pattern();
+            }
+            _ => {}
+        }
+    }
+
+    /// Whether key presses currently belong to a popup, dialog, the input box or the search bar.
+    pub fn captures_input(&self) -> bool {
+        self.error_log.visible
+            || self.author.is_some()
+            || self.input.is_some()
+            || self.dialog.is_some()
+            || self.search.as_ref().is_some_and(|s| s.editing)
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) -> Option<Action> {
+        if self.error_log.visible {
+            self.handle_error_log_key(key);
+            return None;
+        }
+        if self.author.is_some() {
+            self.handle_author_key(key);
+            return None;
+        }
+        if self.input.is_some() {
+            return self.handle_input_key(key);
+        }
+        if self.dialog.is_some() {
+            return self.handle_dialog_key(key);
+        }
+        if self.selected.is_some() {
+            return self.handle_selection_key(key);
+        }
+        if self.search.as_ref().is_some_and(|s| s.editing) {
+            self.handle_search_key(key);
+            return None;
+        }
+        // leave the search before anything else Esc might do
+        if key.code == KeyCode::Esc && self.search.take().is_some() {
+            return None;
+        }
+
+        let page = self.scroll_state.visible_rows.max(1);
+        let action = self.keymap.action(KeyMode::Chat, key.code)?;
+        match action {
+            KeyAction::Quit => return Some(Action::Quit),
+            KeyAction::Compose => self.open_input(),
+            KeyAction::Select => self.start_selection(),
+            KeyAction::TogglePoll => self.poll_collapsed = !self.poll_collapsed,
+            KeyAction::ErrorLog => {
+                self.error_log.visible = true;
+                self.error_log.scroll_offset = 0;
+            }
+            KeyAction::ScrollUp => {
                 self.scroll_up(1);
                 self.scroll_state.auto_scroll = false;
             }
-            KeyCode::Down => {
+            KeyAction::ScrollDown => {
                 self.scroll_down(1);
                 self.scroll_state.auto_scroll = self.scroll_state.scroll_offset == 0;
             }
-            KeyCode::PageUp => {
+            KeyAction::PageUp => {
                 self.scroll_up(page);
                 self.scroll_state.auto_scroll = false;
             }
-            KeyCode::PageDown => {
+            KeyAction::PageDown => {
                 self.scroll_down(page);
                 self.scroll_state.auto_scroll = self.scroll_state.scroll_offset == 0;
             }
-            KeyCode::Home => {
+            KeyAction::Top => {
                 self.scroll_state.scroll_offset = self.scroll_state.max_scroll_rows;
                 self.scroll_state.auto_scroll = false;
             }
-            KeyCode::End => {
+            KeyAction::Bottom => {
                 self.scroll_state.scroll_offset = 0;
                 self.scroll_state.auto_scroll = true;
             }
-            _ => {}
+            KeyAction::Search => self.search = Some(Search::new()),
+            KeyAction::NextMatch => self.jump_to_match(true),
+            KeyAction::PrevMatch => self.jump_to_match(false),
+            KeyAction::CycleFilter => self.set_filter(self.filter.next()),
+            // app-wide keys with a single stream, or selection only
+            _ => return None,
         }
 
         self.scroll_state.scroll_offset = self
@@ -97,6 +848,128 @@ impl AppState {
             .scroll_offset
             .min(self.scroll_state.max_scroll_rows);
 
-        false
+        let at_top = self.scroll_state.scroll_offset == self.scroll_state.max_scroll_rows;
+        if at_top
+            && !self.history_exhausted
+            && matches!(
+                action,
+                KeyAction::ScrollUp | KeyAction::PageUp | KeyAction::Top
+            )
+        {
+            return Some(Action::LoadHistory);
+        }
+
+        None
+    }
+}
+
+/// Parses durations like "90", "30s", "5m" or "1h" into seconds, capped at `MAX_TIMEOUT_SECS`.
+fn parse_duration(input: &str) -> Option<u64> {
+    let input = input.trim();
+    let (digits, unit) = match input.char_indices().last()? {
+        (i, c) if c.is_ascii_alphabetic() => (&input[..i], c),
+        _ => (input, 's'),
+    };
+    let value: u64 = digits.parse().ok()?;
+    let secs = match unit {
+        's' => Some(value),
+        'm' => value.checked_mul(60),
+        'h' => value.checked_mul(3600),
+        _ => return None,
+    };
+    // too large to count is still a valid request for the longest timeout
+    let secs = secs.unwrap_or(u64::MAX).min(MAX_TIMEOUT_SECS);
+    (secs > 0).then_some(secs)
+}
+
+fn format_duration(secs: u64) -> String {
+    match secs {
+        s if s < 60 => format!("{}s", s),
+        s if s < 3600 => format!("{}m", s / 60),
+        s if s % 3600 == 0 => format!("{}h", s / 3600),
+        s => format!("{}h {}m", s / 3600, s % 3600 / 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::time::{TimeStyle, Zone};
+
+    #[test]
+    fn parses_timeout_durations() {
+        assert_eq!(parse_duration("90"), Some(90));
+        assert_eq!(parse_duration(" 5m "), Some(300));
+        assert_eq!(parse_duration("1h"), Some(3600));
+        assert_eq!(parse_duration("0s"), None);
+        assert_eq!(parse_duration("5d"), None);
+        assert_eq!(parse_duration("48h"), Some(MAX_TIMEOUT_SECS));
+        assert_eq!(
+            parse_duration("999999999999999999h"),
+            Some(MAX_TIMEOUT_SECS)
+        );
+    }
+
+    fn state() -> AppState {
+        let time_format = TimeFormat {
+            zone: Zone::Local,
+            hour12: false,
+            style: TimeStyle::Clock,
+        };
+        AppState::new("test".to_string(), true, time_format, &Config::default())
+    }
+
+    #[test]
+    fn echo_is_dropped_when_the_stream_was_faster() {
+        let mut state = state();
+        state.push_local_echo(0, "local-1".to_string(), "hi".to_string());
+        state.push_message(ChatMessage {
+            id: "yt-1".to_string(),
+            author: "me".to_string(),
+            author_channel_id: "UC1".to_string(),
+            delivery: None,
+            ..state.messages[0].clone()
+        });
+        state.apply_outgoing(OutgoingResult::Sent {
+            local_id: "local-1".to_string(),
+            message_id: "yt-1".to_string(),
+        });
+
+        assert_eq!(state.messages.len(), 1);
+        assert_eq!(state.messages[0].author, "me");
+    }
+
+    #[test]
+    fn scrolled_back_view_is_capped() {
+        let mut state = state();
+        state.scroll_state.auto_scroll = false;
+        for i in 0..3 * state.max_messages {
+            state.push_local_echo(0, format!("local-{}", i), "hi".to_string());
+        }
+
+        assert_eq!(state.messages.len(), 2 * state.max_messages);
+    }
+
+    #[test]
+    fn paged_in_history_is_kept_while_scrolled_back() {
+        let mut state = state();
+        state.push_local_echo(0, "local-0".to_string(), "live".to_string());
+        state.scroll_state.auto_scroll = false;
+        let mut older = state.messages[0].clone();
+        older.message = "archived".to_string();
+        state.prepend_history(vec![older; 300]);
+        for i in 1..3 * state.max_messages {
+            state.push_local_echo(0, format!("local-{}", i), "live".to_string());
+        }
+
+        assert_eq!(state.messages.len(), 2 * state.max_messages + 300);
+        assert_eq!(state.messages[0].message, "archived");
+        assert_eq!(state.messages[300].message, "live");
+
+        // following again trims back down, history first
+        state.scroll_state.auto_scroll = true;
+        state.push_local_echo(0, "local-last".to_string(), "live".to_string());
+        assert_eq!(state.messages.len(), state.max_messages);
+        assert_eq!(state.history_len, 0);
     }
 }
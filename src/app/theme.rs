@@ -0,0 +1,188 @@
+use ratatui::style::Color;
+use serde::Deserialize;
+
+/// Colors of the TUI, picked by name with `theme` in the config file.
+#[derive(Debug, Clone)]
+pub struct Theme {
+    pub bg: Color,
+    pub border: Color,
+    pub text: Color,
+    pub text_muted: Color,
+    pub sub_bg: Color,
+    pub poll_active: Color,
+    /// Errors without a more specific category, the others follow.
+    pub error: Color,
+    pub error_quota: Color,
+    pub error_auth: Color,
+    pub error_not_found: Color,
+    pub error_network: Color,
+    pub selected: Color,
+    /// Background of search matches.
+    pub highlight: Color,
+    /// Background of the matches in the message `n` and `N` jumped to.
+    pub current_match: Color,
+    /// Author names by role, anyone else gets a color from `nick_palette`.
+    pub owner: Color,
+    pub moderator: Color,
+    pub member: Color,
+    /// Key hints in the bottom line.
+    pub help: Color,
+    /// Author names are hashed into this, so a name keeps its color across sessions.
+    pub nick_palette: Vec<Color>,
+    /// Badges marking which stream a message is from, by stream.
+    pub stream_palette: Vec<Color>,
+    /// Super Chat and Super Sticker blocks by YouTube's tier, starting at tier 1.
+    /// Higher tiers use the last color.
+    pub tier_palette: Vec<Color>,
+}
+
+impl Theme {
+    pub const BUILTIN: &[&str] = &["default", "light"];
+
+    pub fn builtin(name: &str) -> Option<Theme> {
+        match name {
+            "default" => Some(Theme::default()),
+            "light" => Some(Theme::light()),
+            _ => None,
+        }
+    }
+
+    fn light() -> Theme {
+        Theme {
+            bg: Color::Rgb(248, 249, 252),
+            border: Color::Rgb(124, 58, 237),
+            text: Color::Rgb(30, 32, 44),
+            text_muted: Color::Rgb(107, 114, 128),
+            sub_bg: Color::Rgb(237, 240, 247),
+            poll_active: Color::Rgb(5, 150, 105),
+            error: Color::Rgb(220, 38, 38),
+            error_quota: Color::Rgb(180, 83, 9),
+            error_auth: Color::Rgb(234, 88, 12),
+            error_not_found: Color::Rgb(126, 34, 206),
+            error_network: Color::Rgb(2, 132, 199),
+            selected: Color::Rgb(221, 214, 254),
+            highlight: Color::Rgb(253, 224, 71),
+            current_match: Color::Rgb(249, 115, 22),
+            owner: Color::Rgb(202, 138, 4),
+            moderator: Color::Rgb(37, 99, 235),
+            member: Color::Rgb(5, 150, 105),
+            help: Color::Rgb(120, 127, 142),
+            nick_palette: vec![
+                Color::Rgb(8, 145, 178),
+                Color::Rgb(2, 132, 199),
+                Color::Rgb(37, 99, 235),
+                Color::Rgb(109, 40, 217),
+                Color::Rgb(147, 51, 234),
+                Color::Rgb(219, 39, 119),
+                Color::Rgb(225, 29, 72),
+                Color::Rgb(234, 88, 12),
+                Color::Rgb(161, 98, 7),
+                Color::Rgb(77, 124, 15),
+                Color::Rgb(4, 120, 87),
+                Color::Rgb(15, 118, 110),
+            ],
+            stream_palette: vec![
+                Color::Rgb(8, 145, 178),
+                Color::Rgb(161, 98, 7),
+                Color::Rgb(219, 39, 119),
+                Color::Rgb(4, 120, 87),
+                Color::Rgb(234, 88, 12),
+                Color::Rgb(79, 70, 229),
+            ],
+            tier_palette: vec![
+                Color::Rgb(21, 101, 192),
+                Color::Rgb(0, 131, 143),
+                Color::Rgb(0, 137, 123),
+                Color::Rgb(161, 98, 7),
+                Color::Rgb(230, 81, 0),
+                Color::Rgb(194, 24, 91),
+                Color::Rgb(198, 40, 40),
+            ],
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            bg: Color::Rgb(35, 39, 65),
+            border: Color::Rgb(186, 104, 255),
+            text: Color::Rgb(206, 212, 228),
+            text_muted: Color::Rgb(123, 131, 152),
+            sub_bg: Color::Rgb(28, 35, 58),
+            poll_active: Color::Rgb(110, 231, 183),
+            error: Color::Rgb(248, 113, 113),
+            error_quota: Color::Rgb(251, 191, 36),
+            error_auth: Color::Rgb(251, 146, 60),
+            error_not_found: Color::Rgb(216, 180, 254),
+            error_network: Color::Rgb(125, 211, 252),
+            selected: Color::Rgb(55, 62, 99),
+            highlight: Color::Rgb(250, 204, 21),
+            current_match: Color::Rgb(251, 146, 60),
+            owner: Color::Rgb(250, 204, 21),
+            moderator: Color::Rgb(96, 165, 250),
+            member: Color::Rgb(52, 211, 153),
+            help: Color::Rgb(106, 112, 128),
+            nick_palette: vec![
+                Color::Rgb(103, 232, 249),
+                Color::Rgb(125, 211, 252),
+                Color::Rgb(147, 197, 253),
+                Color::Rgb(196, 181, 253),
+                Color::Rgb(216, 180, 254),
+                Color::Rgb(249, 168, 212),
+                Color::Rgb(253, 164, 175),
+                Color::Rgb(251, 146, 60),
+                Color::Rgb(250, 204, 21),
+                Color::Rgb(190, 242, 100),
+                Color::Rgb(110, 231, 183),
+                Color::Rgb(45, 212, 191),
+                Color::Rgb(244, 114, 182),
+                Color::Rgb(251, 191, 36),
+                Color::Rgb(52, 211, 153),
+                Color::Rgb(129, 140, 248),
+            ],
+            stream_palette: vec![
+                Color::Rgb(103, 232, 249),
+                Color::Rgb(250, 204, 21),
+                Color::Rgb(244, 114, 182),
+                Color::Rgb(110, 231, 183),
+                Color::Rgb(251, 146, 60),
+                Color::Rgb(129, 140, 248),
+            ],
+            tier_palette: vec![
+                Color::Rgb(30, 136, 229),
+                Color::Rgb(0, 229, 255),
+                Color::Rgb(29, 233, 182),
+                Color::Rgb(255, 202, 40),
+                Color::Rgb(245, 124, 0),
+                Color::Rgb(233, 30, 99),
+                Color::Rgb(230, 33, 23),
+            ],
+        }
+    }
+}
+
+/// Symbols shown in front of author names, `[badges]` in the config. An empty
+/// string hides that badge.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Badges {
+    pub owner: String,
+    pub moderator: String,
+    pub verified: String,
+    pub member: String,
+    /// Follow the member badge with the membership level name once it's known.
+    pub member_level: bool,
+}
+
+impl Default for Badges {
+    fn default() -> Self {
+        Self {
+            owner: "👑".to_string(),
+            moderator: "🔧".to_string(),
+            verified: "✔".to_string(),
+            member: "⭐".to_string(),
+            member_level: true,
+        }
+    }
+}
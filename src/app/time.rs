@@ -0,0 +1,154 @@
+use chrono::{DateTime, Local, Utc};
+use chrono_tz::Tz;
+use clap::ValueEnum;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TimeStyle {
+    /// Hours and minutes.
+    Clock,
+    /// Hours, minutes and seconds.
+    Seconds,
+    /// How long ago, e.g. "2m ago".
+    Relative,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Zone {
+    Local,
+    Named(Tz),
+}
+
+/// How message and notification times are shown, shared by every view.
+#[derive(Debug, Clone, Copy)]
+pub struct TimeFormat {
+    pub zone: Zone,
+    pub hour12: bool,
+    pub style: TimeStyle,
+}
+
+impl TimeFormat {
+    pub fn format(&self, at: DateTime<Utc>) -> String {
+        match self.style {
+            TimeStyle::Clock => self.clock(at, false),
+            TimeStyle::Seconds => self.clock(at, true),
+            TimeStyle::Relative => relative(at, Utc::now()),
+        }
+    }
+
+    /// Wall clock time regardless of the style, for things like "ended at".
+    pub fn clock(&self, at: DateTime<Utc>, seconds: bool) -> String {
+        let pattern = match (self.hour12, seconds) {
+            (false, false) => "%H:%M",
+            (false, true) => "%H:%M:%S",
+            (true, false) => "%I:%M %p",
+            (true, true) => "%I:%M:%S %p",
+        };
+        self.in_zone(at, pattern)
+    }
+
+    /// Date and wall clock time, for times that may be days back.
+    pub fn date_time(&self, at: DateTime<Utc>) -> String {
+        format!("{} {}", self.in_zone(at, "%Y-%m-%d"), self.clock(at, false))
+    }
+
+    fn in_zone(&self, at: DateTime<Utc>, pattern: &str) -> String {
+        match self.zone {
+            Zone::Local => at.with_timezone(&Local).format(pattern).to_string(),
+            Zone::Named(tz) => at.with_timezone(&tz).format(pattern).to_string(),
+        }
+    }
+
+    pub fn cycle_style(&mut self) {
+        self.style = match self.style {
+            TimeStyle::Clock => TimeStyle::Seconds,
+            TimeStyle::Seconds => TimeStyle::Relative,
+            TimeStyle::Relative => TimeStyle::Clock,
+        };
+    }
+}
+
+fn relative(at: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    let secs = (now - at).num_seconds().max(0);
+    match secs {
+        0..5 => "now".to_string(),
+        5..60 => format!("{}s ago", secs),
+        60..3_600 => format!("{}m ago", secs / 60),
+        3_600..86_400 => format!("{}h ago", secs / 3_600),
+        _ => format!("{}d ago", secs / 86_400),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(hour: u32, min: u32, sec: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 3, 8, hour, min, sec).unwrap()
+    }
+
+    fn format(zone: Tz, hour12: bool) -> TimeFormat {
+        TimeFormat {
+            zone: Zone::Named(zone),
+            hour12,
+            style: TimeStyle::Clock,
+        }
+    }
+
+    #[test]
+    fn clock_in_12_and_24_hours() {
+        let t = at(14, 5, 9);
+        assert_eq!(format(Tz::UTC, false).clock(t, false), "14:05");
+        assert_eq!(format(Tz::UTC, false).clock(t, true), "14:05:09");
+        assert_eq!(format(Tz::UTC, true).clock(t, false), "02:05 PM");
+        assert_eq!(format(Tz::UTC, true).clock(t, true), "02:05:09 PM");
+        assert_eq!(format(Tz::UTC, true).clock(at(0, 30, 0), false), "12:30 AM");
+    }
+
+    #[test]
+    fn clock_in_named_zones() {
+        let t = at(14, 5, 9);
+        // daylight saving time started in New York earlier that morning
+        assert_eq!(
+            format(Tz::America__New_York, false).clock(t, false),
+            "10:05"
+        );
+        assert_eq!(format(Tz::Asia__Kolkata, false).clock(t, false), "19:35");
+        assert_eq!(
+            format(Tz::Asia__Tokyo, false).date_time(at(23, 30, 0)),
+            "2026-03-09 08:30"
+        );
+    }
+
+    #[test]
+    fn relative_rounds_down_to_the_largest_unit() {
+        let now = at(12, 0, 0);
+        let ago = |secs: i64| relative(now - chrono::Duration::seconds(secs), now);
+        assert_eq!(ago(0), "now");
+        assert_eq!(ago(4), "now");
+        assert_eq!(ago(5), "5s ago");
+        assert_eq!(ago(59), "59s ago");
+        assert_eq!(ago(60), "1m ago");
+        assert_eq!(ago(3 * 60 + 59), "3m ago");
+        assert_eq!(ago(3_599), "59m ago");
+        assert_eq!(ago(3_600), "1h ago");
+        assert_eq!(ago(86_399), "23h ago");
+        assert_eq!(ago(86_400), "1d ago");
+        assert_eq!(ago(10 * 86_400), "10d ago");
+        // clock skew can put a message slightly in the future
+        assert_eq!(ago(-30), "now");
+    }
+
+    #[test]
+    fn style_cycles_back_to_clock() {
+        let mut format = format(Tz::UTC, false);
+        format.cycle_style();
+        assert_eq!(format.style, TimeStyle::Seconds);
+        assert_eq!(format.format(at(14, 5, 9)), "14:05:09");
+        format.cycle_style();
+        assert_eq!(format.style, TimeStyle::Relative);
+        format.cycle_style();
+        assert_eq!(format.style, TimeStyle::Clock);
+        assert_eq!(format.format(at(14, 5, 9)), "14:05");
+    }
+}
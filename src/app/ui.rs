@@ -1,182 +1,1123 @@
-use crate::app::event::{ChatMessage, MessageKind};
-use crate::app::state::AppState;
+use crate::app::author::{AuthorDetails, LARGE_AVATAR_COLS, LARGE_AVATAR_ROWS};
+use crate::app::event::{
+    Avatar, ChatMessage, Delivery, DisconnectReason, ErrorCategory, MessageKind, Poll, PollStatus,
+    StatusEvent, StreamId,
+};
+use crate::app::filter::Filter;
+use crate::app::graphics::slot_style;
+use crate::app::keymap::KeyAction;
+use crate::app::search::Search;
+use crate::app::state::{AppState, Dialog, MAX_INPUT_CHARS, Notification};
+use crate::app::theme::Theme;
 use ratatui::Frame;
-use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
 use ratatui::style::{Color, Modifier, Style};
 use ratatui::text::{Line, Span};
-use ratatui::widgets::{Block, Borders, List, ListItem, Paragraph, Wrap};
-
-const COLOR_BG: Color = Color::Rgb(35, 39, 65);
-const COLOR_BORDER: Color = Color::Rgb(186, 104, 255);
-const COLOR_TEXT: Color = Color::Rgb(206, 212, 228);
-const COLOR_TEXT_MUTED: Color = Color::Rgb(123, 131, 152);
-const COLOR_SUB_BG: Color = Color::Rgb(28, 35, 58);
-const AVATAR_PLACEHOLDER_UNICODE: char = '\u{10EEEE}';
-
-fn nick_color(name: &str) -> Color {
-    let palette = [
-        Color::Rgb(103, 232, 249),
-        Color::Rgb(125, 211, 252),
-        Color::Rgb(147, 197, 253),
-        Color::Rgb(196, 181, 253),
-        Color::Rgb(216, 180, 254),
-        Color::Rgb(249, 168, 212),
-        Color::Rgb(253, 164, 175),
-        Color::Rgb(251, 146, 60),
-        Color::Rgb(250, 204, 21),
-        Color::Rgb(190, 242, 100),
-        Color::Rgb(110, 231, 183),
-        Color::Rgb(45, 212, 191),
-        Color::Rgb(244, 114, 182),
-        Color::Rgb(251, 191, 36),
-        Color::Rgb(52, 211, 153),
-        Color::Rgb(129, 140, 248),
-    ];
+use ratatui::widgets::{Block, Borders, Clear, List, ListItem, Paragraph, Wrap};
+use std::ops::RangeInclusive;
+use std::time::Duration;
+
+const TOAST_LIFETIME: Duration = Duration::from_secs(6);
+const MAX_TOASTS: usize = 3;
+const AUTHOR_HEADER_ROWS: u16 = 6;
+const STREAM_BADGE: &str = "▌ ";
+const STREAM_BADGE_WIDTH: usize = 2;
+
+fn stream_color(stream: StreamId, theme: &Theme) -> Color {
+    theme.stream_palette[stream % theme.stream_palette.len()]
+}
+
+fn nick_color(name: &str, theme: &Theme) -> Color {
     let hash = name.bytes().fold(0usize, |acc, b| {
         acc.wrapping_mul(31).wrapping_add(b as usize)
     });
-    palette[hash % palette.len()]
+    theme.nick_palette[hash % theme.nick_palette.len()]
 }
 
-fn u32_to_color(value: u32) -> Color {
-    Color::Rgb(
-        ((value >> 16) & 0xFF) as u8,
-        ((value >> 8) & 0xFF) as u8,
-        (value & 0xFF) as u8,
-    )
+fn tier_color(tier: u32, theme: &Theme) -> Color {
+    let index = (tier.max(1) - 1) as usize;
+    theme.tier_palette[index.min(theme.tier_palette.len() - 1)]
 }
 
-fn build_original_line(text: String, m: &ChatMessage) -> ListItem {
-    ListItem::new(Line::from(vec![
-        if let Some(avatar) = &m.avatar {
-            let avatar_placeholder: String =
-                std::iter::repeat_n(AVATAR_PLACEHOLDER_UNICODE, avatar.cols as usize).collect();
-            Span::styled(
-                avatar_placeholder,
-                Style::default().fg(u32_to_color(avatar.id)),
-            )
-        } else {
-            Span::raw("")
-        },
-        Span::styled(
-            format!("[{}]", m.timestamp),
-            Style::default().fg(COLOR_TEXT_MUTED),
-        ),
-        Span::raw(if m.is_member { " ⭐ " } else { " " }),
+fn body_style(m: &ChatMessage, theme: &Theme) -> Style {
+    match (m.removed, m.delivery) {
+        (Some(_), _) => Style::default()
+            .fg(theme.text_muted)
+            .add_modifier(Modifier::CROSSED_OUT),
+        (None, Some(Delivery::Pending)) => Style::default()
+            .fg(theme.text_muted)
+            .add_modifier(Modifier::ITALIC),
+        (None, Some(Delivery::Failed)) => Style::default().fg(theme.error),
+        (None, None) => Style::default().fg(theme.text),
+    }
+}
+
+/// Blank slot the graphics backend draws the author's avatar into.
+fn avatar_span(m: &ChatMessage) -> Span<'static> {
+    match &m.avatar {
+        Some(avatar) => Span::styled(" ".repeat(avatar.cols as usize), slot_style(avatar.id, 0)),
+        None => Span::raw(""),
+    }
+}
+
+/// A slot for the popup's larger copy of `avatar`.
+fn large_avatar_lines(avatar: &Avatar) -> Vec<Line<'static>> {
+    (0..LARGE_AVATAR_ROWS)
+        .map(|row| {
+            Line::from(Span::styled(
+                " ".repeat(LARGE_AVATAR_COLS as usize),
+                slot_style(avatar.id, row),
+            ))
+        })
+        .collect()
+}
+
+/// `m.message` wrapped to `width`, in the body style of `m` with search matches
+/// highlighted. Matches are found in the whole message and then cut at the wrap points,
+/// so anchored patterns and matches spanning two lines come out right.
+fn body_lines(m: &ChatMessage, width: usize, app: &AppState) -> Vec<Vec<Span<'static>>> {
+    let style = body_style(m, &app.theme);
+    let wrapped = textwrap::wrap(&m.message, width);
+    let Some(search) = &app.search else {
+        return wrapped
+            .into_iter()
+            .map(|part| vec![Span::styled(part.into_owned(), style)])
+            .collect();
+    };
+
+    let current = search
+        .current
+        .and_then(|i| app.messages.get(i))
+        .is_some_and(|current| std::ptr::eq(current, m));
+    let match_style = style.fg(app.theme.bg).bg(if current {
+        app.theme.current_match
+    } else {
+        app.theme.highlight
+    });
+    let ranges: Vec<(usize, usize)> = search.ranges(&m.message).collect();
+
+    let text = m.message.as_str();
+    let mut from = 0;
+    wrapped
+        .into_iter()
+        .map(|part| {
+            // wrapped lines are slices of the message, in order
+            let Some(start) = text[from..].find(part.as_ref()).map(|i| from + i) else {
+                return vec![Span::styled(part.into_owned(), style)];
+            };
+            let end = start + part.len();
+            from = end;
+
+            let mut spans = Vec::new();
+            let mut last = start;
+            for &(match_start, match_end) in ranges.iter().filter(|(s, e)| *s < end && *e > start) {
+                let (match_start, match_end) = (match_start.max(start), match_end.min(end));
+                if match_start > last {
+                    spans.push(Span::styled(text[last..match_start].to_string(), style));
+                }
+                spans.push(Span::styled(
+                    text[match_start..match_end].to_string(),
+                    match_style,
+                ));
+                last = match_end;
+            }
+            if last < end || spans.is_empty() {
+                spans.push(Span::styled(text[last..end].to_string(), style));
+            }
+            spans
+        })
+        .collect()
+}
+
+fn author_color(m: &ChatMessage, theme: &Theme) -> Color {
+    if m.is_owner {
+        theme.owner
+    } else if m.is_moderator {
+        theme.moderator
+    } else if m.is_member {
+        theme.member
+    } else {
+        nick_color(&m.author, theme)
+    }
+}
+
+/// Author name in its role color, the owner's name is set on a colored background.
+fn author_style(m: &ChatMessage, theme: &Theme) -> Style {
+    let style = Style::default().add_modifier(Modifier::BOLD);
+    if m.is_owner {
+        style.fg(theme.bg).bg(theme.owner)
+    } else {
+        style.fg(author_color(m, theme))
+    }
+}
+
+/// Role badges in front of the author name, each followed by a space.
+fn badge_spans(m: &ChatMessage, app: &AppState) -> Vec<Span<'static>> {
+    let (badges, theme) = (&app.badges, &app.theme);
+    let member = match m.member_level.as_deref() {
+        Some(level) if badges.member_level && !badges.member.is_empty() => {
+            format!("{} {}", badges.member, level)
+        }
+        _ => badges.member.clone(),
+    };
+    [
+        (m.is_owner, badges.owner.as_str(), theme.owner),
+        (m.is_moderator, badges.moderator.as_str(), theme.moderator),
+        (m.is_verified, badges.verified.as_str(), theme.text_muted),
+        (m.is_member, member.as_str(), theme.member),
+    ]
+    .into_iter()
+    .filter(|(has_role, badge, _)| *has_role && !badge.is_empty())
+    .map(|(_, badge, color)| Span::styled(format!("{} ", badge), Style::default().fg(color)))
+    .collect()
+}
+
+fn build_original_line<'a>(
+    body: Vec<Span<'static>>,
+    m: &'a ChatMessage,
+    app: &AppState,
+) -> Line<'a> {
+    let theme = &app.theme;
+    let mut spans = vec![
+        avatar_span(m),
         Span::styled(
-            format!("{}:", m.author),
-            Style::default()
-                .fg(nick_color(&m.author))
-                .add_modifier(Modifier::BOLD),
+            format!("[{}] ", app.time_format.format(m.timestamp)),
+            Style::default().fg(theme.text_muted),
         ),
+    ];
+    spans.extend(badge_spans(m, app));
+    spans.extend([
+        Span::styled(m.author.clone(), author_style(m, theme)),
+        Span::styled(":", Style::default().add_modifier(Modifier::BOLD)),
         Span::raw(" "),
-        Span::styled(text, Style::default().fg(COLOR_TEXT)),
-    ]))
+    ]);
+    spans.extend(body);
+    Line::from(spans)
 }
 
-fn build_lines(m: &ChatMessage, chat_width: usize) -> Vec<ListItem> {
+fn build_lines<'a>(m: &'a ChatMessage, chat_width: usize, app: &AppState) -> Vec<Line<'a>> {
+    let theme = &app.theme;
     let avatar_width = m.avatar.as_ref().map(|a| a.cols as usize).unwrap_or(0);
-    let prefix = format!("[{}] {}: ", m.timestamp, m.author);
-    let prefix_len = avatar_width + prefix.chars().count();
+    let prefix = format!("[{}] {}: ", app.time_format.format(m.timestamp), m.author);
+    let badges_width: usize = badge_spans(m, app).iter().map(Span::width).sum();
+    let prefix_len = avatar_width + badges_width + prefix.chars().count();
     let body_width = chat_width.saturating_sub(prefix_len).max(1);
-    let wrapped = textwrap::wrap(&m.message, body_width);
+    let mut body = body_lines(m, body_width, app).into_iter();
 
-    let mut lines = Vec::with_capacity(wrapped.len().max(1));
+    let first = body
+        .next()
+        .unwrap_or_else(|| vec![Span::styled(m.message.clone(), body_style(m, theme))]);
+    let mut lines = vec![build_original_line(first, m, app)];
+    let indent = " ".repeat(prefix_len);
 
-    if wrapped.is_empty() {
-        lines.push(build_original_line(m.message.clone(), m));
+    for part in body {
+        let mut spans = vec![Span::styled(
+            indent.clone(),
+            Style::default().fg(theme.text),
+        )];
+        spans.extend(part);
+        lines.push(Line::from(spans));
+    }
 
-        return lines;
+    lines
+}
+
+fn build_paid_lines<'a>(
+    m: &'a ChatMessage,
+    amount: &str,
+    tier: u32,
+    chat_width: usize,
+    app: &AppState,
+) -> Vec<Line<'a>> {
+    let theme = &app.theme;
+    let color = tier_color(tier, theme);
+    let header_style = Style::default().fg(theme.bg).bg(color);
+    let mut lines = vec![Line::from(vec![
+        avatar_span(m),
+        Span::styled(
+            format!(" [{}] ", app.time_format.format(m.timestamp)),
+            header_style,
+        ),
+        Span::styled(
+            format!("{} ", m.author),
+            header_style.add_modifier(Modifier::BOLD),
+        ),
+        Span::styled(
+            format!("{} ", amount),
+            header_style.add_modifier(Modifier::BOLD),
+        ),
+    ])];
+
+    let body_width = chat_width.saturating_sub(2).max(1);
+    for part in body_lines(m, body_width, app) {
+        let mut spans = vec![Span::styled("▌ ", Style::default().fg(color))];
+        spans.extend(part);
+        lines.push(Line::from(spans));
     }
 
-    lines.push(build_original_line(wrapped[0].to_string(), m));
-    let indent = " ".repeat(prefix_len);
+    lines
+}
 
-    for part in wrapped.iter().skip(1) {
-        lines.push(ListItem::new(Line::from(vec![
-            Span::styled(indent.clone(), Style::default().fg(COLOR_TEXT)),
-            Span::styled(part.to_string(), Style::default().fg(COLOR_TEXT)),
-        ])));
+fn membership_headline(kind: &MessageKind) -> String {
+    match kind {
+        MessageKind::Subscription {
+            level,
+            is_upgrade: true,
+        } => {
+            format!("upgraded membership to {}!", level)
+        }
+        MessageKind::Subscription { level, .. } if level.is_empty() => {
+            "became a member!".to_string()
+        }
+        MessageKind::Subscription { level, .. } => format!("joined {}!", level),
+        MessageKind::MemberMilestone { months, .. } => {
+            format!("member for {} months", months)
+        }
+        MessageKind::MembershipGifting { count, level } => {
+            format!("gifted {} {} memberships", count, level)
+        }
+        MessageKind::GiftMembershipReceived {
+            gifter: Some(gifter),
+            ..
+        } => {
+            format!("received a gift membership from {}", gifter)
+        }
+        MessageKind::GiftMembershipReceived { .. } => "received a gift membership".to_string(),
+        _ => String::new(),
+    }
+}
+
+fn build_subscription_lines<'a>(
+    m: &'a ChatMessage,
+    chat_width: usize,
+    app: &AppState,
+) -> Vec<Line<'a>> {
+    let theme = &app.theme;
+    let mut lines = vec![Line::from(vec![
+        Span::styled(
+            format!(" {} ", m.author),
+            Style::default()
+                .fg(author_color(m, theme))
+                .bg(theme.sub_bg)
+                .add_modifier(Modifier::BOLD),
+        ),
+        Span::styled(
+            format!("{} ", membership_headline(&m.kind)),
+            Style::default().fg(theme.text).bg(theme.sub_bg),
+        ),
+    ])];
+
+    let body_width = chat_width.saturating_sub(2).max(1);
+    for part in body_lines(m, body_width, app) {
+        let mut spans = vec![Span::styled("▌ ", Style::default().fg(theme.border))];
+        spans.extend(part);
+        lines.push(Line::from(spans));
     }
 
     lines
 }
 
-// todo: remove duplication?
-fn row_count_for_message(m: &ChatMessage, chat_width: usize) -> usize {
-    match m.kind {
-        MessageKind::Text => {
-            let prefix = format!("[{}] {}: ", m.timestamp, m.author);
-            let prefix_len = prefix.chars().count();
-            let body_width = chat_width.saturating_sub(prefix_len).max(1);
-            let wrapped = textwrap::wrap(&m.message, body_width);
-            wrapped.len().max(1)
+fn build_system_lines<'a>(m: &'a ChatMessage, chat_width: usize, app: &AppState) -> Vec<Line<'a>> {
+    let theme = &app.theme;
+    let prefix = format!("[{}] ", app.time_format.format(m.timestamp));
+    let body_width = chat_width.saturating_sub(prefix.chars().count()).max(1);
+    let style = Style::default()
+        .fg(theme.text_muted)
+        .add_modifier(Modifier::ITALIC);
+
+    textwrap::wrap(&m.message, body_width)
+        .into_iter()
+        .enumerate()
+        .map(|(i, part)| {
+            let lead = if i == 0 {
+                prefix.clone()
+            } else {
+                " ".repeat(prefix.chars().count())
+            };
+            Line::from(vec![
+                Span::styled(lead, Style::default().fg(theme.text_muted)),
+                Span::styled(part.to_string(), style),
+            ])
+        })
+        .collect()
+}
+
+fn build_rows<'a>(m: &'a ChatMessage, chat_width: usize, app: &AppState) -> Vec<Line<'a>> {
+    match &m.kind {
+        MessageKind::Text => build_lines(m, chat_width, app),
+        MessageKind::Subscription { .. }
+        | MessageKind::MemberMilestone { .. }
+        | MessageKind::MembershipGifting { .. }
+        | MessageKind::GiftMembershipReceived { .. } => {
+            build_subscription_lines(m, chat_width, app)
+        }
+        MessageKind::SuperChat { amount, tier } | MessageKind::SuperSticker { amount, tier } => {
+            build_paid_lines(m, amount, *tier, chat_width, app)
         }
-        MessageKind::Subscription => 1,
+        MessageKind::System => build_system_lines(m, chat_width, app),
     }
 }
 
+/// Rows of a message as shown in `app`, with the stream badge in front when enabled.
+fn message_rows<'a>(app: &AppState, m: &'a ChatMessage, chat_width: usize) -> Vec<Line<'a>> {
+    if !app.show_stream_badges {
+        return build_rows(m, chat_width, app);
+    }
+
+    let badge = Span::styled(
+        STREAM_BADGE,
+        Style::default().fg(stream_color(m.stream, &app.theme)),
+    );
+    build_rows(m, chat_width.saturating_sub(STREAM_BADGE_WIDTH), app)
+        .into_iter()
+        .map(|mut line| {
+            line.spans.insert(0, badge.clone());
+            line
+        })
+        .collect()
+}
+
+fn row_count_for_message(app: &AppState, m: &ChatMessage, chat_width: usize) -> usize {
+    message_rows(app, m, chat_width).len()
+}
+
 fn build_title(app: &AppState) -> Line<'static> {
-    Line::from(vec![
-        Span::styled("[ ", Style::default().fg(COLOR_TEXT_MUTED)),
-        Span::styled("Channel", Style::default().fg(COLOR_BORDER)),
-        Span::styled(": ", Style::default().fg(COLOR_TEXT_MUTED)),
-        Span::styled(app.title.clone(), Style::default().fg(COLOR_TEXT)),
-        Span::styled(" ] - [ ", Style::default().fg(COLOR_TEXT_MUTED)),
-        Span::styled("Viewers", Style::default().fg(COLOR_BORDER)),
-        Span::styled(": ", Style::default().fg(COLOR_TEXT_MUTED)),
+    let theme = &app.theme;
+    let mut spans = vec![
+        Span::styled("[ ", Style::default().fg(theme.text_muted)),
+        Span::styled("Channel", Style::default().fg(theme.border)),
+        Span::styled(": ", Style::default().fg(theme.text_muted)),
+        Span::styled(app.title.clone(), Style::default().fg(theme.text)),
+        Span::styled(" ] - [ ", Style::default().fg(theme.text_muted)),
+        Span::styled("Viewers", Style::default().fg(theme.border)),
+        Span::styled(": ", Style::default().fg(theme.text_muted)),
         Span::styled(
             app.stats.viewer_count.to_string(),
-            Style::default().fg(COLOR_TEXT),
+            Style::default().fg(theme.text),
         ),
-        Span::styled(" ]", Style::default().fg(COLOR_TEXT_MUTED)),
-    ])
+        Span::styled(" ]", Style::default().fg(theme.text_muted)),
+    ];
+    if app.filter != Filter::All {
+        spans.extend([
+            Span::styled(" - [ ", Style::default().fg(theme.text_muted)),
+            Span::styled("Filter", Style::default().fg(theme.border)),
+            Span::styled(": ", Style::default().fg(theme.text_muted)),
+            Span::styled(app.filter.label(), Style::default().fg(theme.highlight)),
+            Span::styled(" ]", Style::default().fg(theme.text_muted)),
+        ]);
+    }
+    Line::from(spans)
 }
 
 pub fn max_scroll_for_viewport(app: &AppState, chat_width: usize, visible_rows: usize) -> usize {
     let total_rows = app
         .messages
         .iter()
-        .map(|m| row_count_for_message(m, chat_width))
+        .filter(|m| app.filter.matches(m))
+        .map(|m| row_count_for_message(app, m, chat_width))
         .sum::<usize>();
     total_rows.saturating_sub(visible_rows)
 }
 
-pub fn draw(frame: &mut Frame, app: &AppState) {
+/// Scroll offsets that keep the message at `selected` fully in view.
+pub fn message_scroll_range(
+    app: &AppState,
+    selected: usize,
+    chat_width: usize,
+    visible_rows: usize,
+) -> Option<RangeInclusive<usize>> {
+    let own_rows = row_count_for_message(app, app.messages.get(selected)?, chat_width);
+    let rows_after = app
+        .messages
+        .iter()
+        .skip(selected + 1)
+        .filter(|m| app.filter.matches(m))
+        .map(|m| row_count_for_message(app, m, chat_width))
+        .sum::<usize>();
+
+    Some(
+        (rows_after + own_rows)
+            .saturating_sub(visible_rows)
+            .min(rows_after)..=rows_after,
+    )
+}
+
+pub struct Areas {
+    pub poll: Option<Rect>,
+    pub chat: Rect,
+    pub banner: Option<Rect>,
+    pub input: Option<Rect>,
+    pub help: Rect,
+}
+
+pub fn layout(area: Rect, app: &AppState) -> Areas {
+    let poll_height = match &app.poll {
+        Some(_) if app.poll_collapsed => 3,
+        Some(poll) => poll.options.len() as u16 + 3,
+        None => 0,
+    };
+    let banner_height = match app.status {
+        StatusEvent::Disconnected(_) => 1,
+        _ => 0,
+    };
+    let searching = app.search.as_ref().is_some_and(|s| s.editing);
+    let input_height = if app.input.is_some() || searching {
+        3
+    } else {
+        0
+    };
     let areas = Layout::default()
         .direction(Direction::Vertical)
-        .constraints([Constraint::Min(1), Constraint::Length(1)])
-        .split(frame.area());
+        .constraints([
+            Constraint::Length(poll_height),
+            Constraint::Min(1),
+            Constraint::Length(banner_height),
+            Constraint::Length(input_height),
+            Constraint::Length(1),
+        ])
+        .split(area);
 
-    let visible_rows = areas[0].height.saturating_sub(2) as usize;
-    let chat_width = areas[0].width.saturating_sub(2) as usize;
+    Areas {
+        poll: (poll_height > 0).then_some(areas[0]),
+        chat: areas[1],
+        banner: (banner_height > 0).then_some(areas[2]),
+        input: (input_height > 0).then_some(areas[3]),
+        help: areas[4],
+    }
+}
 
-    let all_rows: Vec<ListItem> = app
-        .messages
-        .iter()
-        .flat_map(|m| match m.kind {
-            MessageKind::Text => {
-                let lines = build_lines(m, chat_width);
+fn poll_option_line(
+    text: &str,
+    tally: i64,
+    total: i64,
+    label_width: usize,
+    bar_width: usize,
+    theme: &Theme,
+) -> Line<'static> {
+    let ratio = if total > 0 {
+        tally as f64 / total as f64
+    } else {
+        0.0
+    };
+    let filled = (ratio * bar_width as f64).round() as usize;
+    let label: String = text.chars().take(label_width).collect();
 
-                lines
-            }
-            MessageKind::Subscription => {
-                let line = Line::from(vec![
+    Line::from(vec![
+        Span::styled(
+            format!("{:<width$} ", label, width = label_width),
+            Style::default().fg(theme.text),
+        ),
+        Span::styled("█".repeat(filled), Style::default().fg(theme.border)),
+        Span::styled(
+            "░".repeat(bar_width.saturating_sub(filled)),
+            Style::default().fg(theme.sub_bg),
+        ),
+        Span::styled(
+            format!(" {} ({:.0}%)", tally, ratio * 100.0),
+            Style::default().fg(theme.text_muted),
+        ),
+    ])
+}
+
+fn draw_poll(frame: &mut Frame, area: Rect, poll: &Poll, collapsed: bool, theme: &Theme) {
+    let (status, status_color) = match poll.status {
+        PollStatus::Active => ("ACTIVE", theme.poll_active),
+        PollStatus::Closed => ("CLOSED", theme.text_muted),
+    };
+    let title = Line::from(vec![
+        Span::styled("[ ", Style::default().fg(theme.text_muted)),
+        Span::styled("Poll", Style::default().fg(theme.border)),
+        Span::styled(": ", Style::default().fg(theme.text_muted)),
+        Span::styled(status, Style::default().fg(status_color)),
+        Span::styled(" ]", Style::default().fg(theme.text_muted)),
+    ]);
+
+    let question = Line::from(Span::styled(
+        poll.question.clone(),
+        Style::default().fg(theme.text).add_modifier(Modifier::BOLD),
+    ));
+    let mut lines = vec![question];
+
+    if !collapsed {
+        let inner_width = area.width.saturating_sub(2) as usize;
+        let total: i64 = poll.options.iter().map(|o| o.tally).sum();
+        let label_width = poll
+            .options
+            .iter()
+            .map(|o| o.text.chars().count())
+            .max()
+            .unwrap_or(0)
+            .min(inner_width / 3);
+        let bar_width = inner_width.saturating_sub(label_width + 16);
+
+        lines.extend(
+            poll.options
+                .iter()
+                .map(|o| poll_option_line(&o.text, o.tally, total, label_width, bar_width, theme)),
+        );
+    }
+
+    let widget = Paragraph::new(lines).block(
+        Block::default()
+            .title(title)
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(theme.border))
+            .style(Style::default().bg(theme.bg)),
+    );
+
+    frame.render_widget(widget, area);
+}
+
+fn build_banner(app: &AppState) -> Option<Line<'static>> {
+    let theme = &app.theme;
+    let StatusEvent::Disconnected(reason) = &app.status else {
+        return None;
+    };
+    let text = match reason {
+        DisconnectReason::StreamEnded { at: Some(at) } => {
+            format!(" ■ stream ended at {} ", app.time_format.clock(*at, false))
+        }
+        DisconnectReason::StreamEnded { at: None } => " ■ stream ended ".to_string(),
+        DisconnectReason::Error(_) => " ✕ connection lost, reconnecting ".to_string(),
+        DisconnectReason::ChatGone => " ✕ live chat not found ".to_string(),
+    };
+
+    Some(Line::from(vec![
+        Span::styled(
+            text,
+            Style::default()
+                .fg(theme.bg)
+                .bg(theme.border)
+                .add_modifier(Modifier::BOLD),
+        ),
+        Span::styled(
+            " chat history is still available",
+            Style::default().fg(theme.text_muted),
+        ),
+    ]))
+}
+
+fn build_status(status: &StatusEvent, theme: &Theme) -> Span<'static> {
+    match status {
+        StatusEvent::Connecting => {
+            Span::styled("[◌ CONNECTING]", Style::default().fg(theme.text_muted))
+        }
+        StatusEvent::Connected => Span::styled("[● LIVE]", Style::default().fg(theme.poll_active)),
+        StatusEvent::Disconnected(DisconnectReason::StreamEnded { .. }) => {
+            Span::styled("[■ ENDED]", Style::default().fg(theme.border))
+        }
+        StatusEvent::Disconnected(DisconnectReason::Error(e)) => Span::styled(
+            format!("[✕ DISCONNECTED: {}]", e),
+            Style::default().fg(theme.error),
+        ),
+        StatusEvent::Disconnected(DisconnectReason::ChatGone) => {
+            Span::styled("[✕ CHAT GONE]", Style::default().fg(theme.error))
+        }
+    }
+}
+
+fn draw_input(frame: &mut Frame, area: Rect, text: &str, theme: &Theme) {
+    let len = text.chars().count();
+    let title = Line::from(vec![
+        Span::styled("[ ", Style::default().fg(theme.text_muted)),
+        Span::styled("Message", Style::default().fg(theme.border)),
+        Span::styled(
+            format!(
+                ": {}/{} ] - [Enter] send - [ESC] cancel",
+                len, MAX_INPUT_CHARS
+            ),
+            Style::default().fg(theme.text_muted),
+        ),
+    ]);
+
+    // keep the end of long messages, where the cursor is, in view
+    let inner_width = area.width.saturating_sub(2) as usize;
+    let visible: String = text
+        .chars()
+        .skip(len.saturating_sub(inner_width.saturating_sub(1)))
+        .collect();
+    let cursor_x = area.x + 1 + visible.chars().count() as u16;
+
+    let input = Paragraph::new(Span::styled(visible, Style::default().fg(theme.text))).block(
+        Block::default()
+            .title(title)
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(theme.border))
+            .style(Style::default().bg(theme.bg)),
+    );
+
+    frame.render_widget(input, area);
+    frame.set_cursor_position((cursor_x, area.y + 1));
+}
+
+fn draw_search(frame: &mut Frame, area: Rect, app: &AppState, search: &Search) {
+    let theme = &app.theme;
+    let status = if search.is_invalid() {
+        Span::styled("invalid regex", Style::default().fg(theme.error))
+    } else {
+        Span::styled(
+            format!("{} matches", app.search_matches().len()),
+            Style::default().fg(theme.text_muted),
+        )
+    };
+    let title = Line::from(vec![
+        Span::styled("[ ", Style::default().fg(theme.text_muted)),
+        Span::styled(
+            if search.regex_mode { "Regex" } else { "Search" },
+            Style::default().fg(theme.border),
+        ),
+        Span::styled(": ", Style::default().fg(theme.text_muted)),
+        status,
+        Span::styled(
+            " ] - [Enter] find - [Tab] text/regex - [ESC] cancel",
+            Style::default().fg(theme.text_muted),
+        ),
+    ]);
+
+    let len = search.query.chars().count();
+    let inner_width = area.width.saturating_sub(2) as usize;
+    let visible: String = search
+        .query
+        .chars()
+        .skip(len.saturating_sub(inner_width.saturating_sub(1)))
+        .collect();
+    let cursor_x = area.x + 1 + visible.chars().count() as u16;
+
+    let input = Paragraph::new(Span::styled(visible, Style::default().fg(theme.text))).block(
+        Block::default()
+            .title(title)
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(theme.border))
+            .style(Style::default().bg(theme.bg)),
+    );
+
+    frame.render_widget(input, area);
+    frame.set_cursor_position((cursor_x, area.y + 1));
+}
+
+fn draw_dialog(frame: &mut Frame, area: Rect, dialog: &Dialog, theme: &Theme) {
+    let (lines, hint) = match dialog {
+        Dialog::Confirm { prompt, .. } => (
+            vec![Line::from(Span::styled(
+                prompt.clone(),
+                Style::default().fg(theme.text),
+            ))],
+            "[y/Enter] confirm - [n/ESC] cancel",
+        ),
+        Dialog::TimeoutDuration { author, input, .. } => (
+            vec![
+                Line::from(Span::styled(
+                    format!("Time out {} for:", author),
+                    Style::default().fg(theme.text),
+                )),
+                Line::from(vec![
                     Span::styled(
-                        format!(" {} ", m.author),
+                        input.clone(),
                         Style::default()
-                            .fg(nick_color(&m.author))
-                            .bg(COLOR_SUB_BG)
+                            .fg(theme.border)
                             .add_modifier(Modifier::BOLD),
                     ),
-                    Span::styled(
-                        format!("{} ", m.message),
-                        Style::default().fg(COLOR_TEXT).bg(COLOR_SUB_BG),
-                    ),
-                ]);
+                    Span::styled("  e.g. 30s, 5m, 1h", Style::default().fg(theme.text_muted)),
+                ]),
+            ],
+            "[Enter] next - [ESC] cancel",
+        ),
+    };
+
+    let width = area.width.saturating_sub(4).min(60);
+    let height = lines.len() as u16 + 2;
+    let area = Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + area.height.saturating_sub(height) / 2,
+        width,
+        height.min(area.height),
+    );
+    let title = Line::from(vec![
+        Span::styled("[ ", Style::default().fg(theme.text_muted)),
+        Span::styled("Moderation", Style::default().fg(theme.border)),
+        Span::styled(
+            format!(" ] - {}", hint),
+            Style::default().fg(theme.text_muted),
+        ),
+    ]);
+    let widget = Paragraph::new(lines).wrap(Wrap { trim: true }).block(
+        Block::default()
+            .title(title)
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(theme.border))
+            .style(Style::default().bg(theme.sub_bg)),
+    );
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(widget, area);
+}
 
-                vec![ListItem::new(line)]
+fn category_label(category: ErrorCategory, theme: &Theme) -> (&'static str, Color) {
+    match category {
+        ErrorCategory::QuotaExceeded => ("QUOTA", theme.error_quota),
+        ErrorCategory::AuthExpired => ("AUTH", theme.error_auth),
+        ErrorCategory::ChatNotFound => ("NOT FOUND", theme.error_not_found),
+        ErrorCategory::Network => ("NETWORK", theme.error_network),
+        ErrorCategory::Other => ("ERROR", theme.error),
+    }
+}
+
+fn notification_line(n: &Notification, app: &AppState) -> Line<'static> {
+    let theme = &app.theme;
+    let (label, color) = category_label(n.error.category, theme);
+    Line::from(vec![
+        Span::styled(
+            format!("[{}] ", app.time_format.clock(n.timestamp, true)),
+            Style::default().fg(theme.text_muted),
+        ),
+        Span::styled(
+            format!("{} ", label),
+            Style::default().fg(color).add_modifier(Modifier::BOLD),
+        ),
+        Span::styled(n.error.message.clone(), Style::default().fg(theme.text)),
+    ])
+}
+
+fn draw_toasts(frame: &mut Frame, area: Rect, app: &AppState) {
+    let theme = &app.theme;
+    let toasts: Vec<&Notification> = app
+        .notifications
+        .iter()
+        .rev()
+        .take_while(|n| n.received_at.elapsed() < TOAST_LIFETIME)
+        .take(MAX_TOASTS)
+        .collect();
+
+    let width = area.width.saturating_sub(2).min(60);
+    for (i, n) in toasts.iter().enumerate() {
+        let y = area.y + 1 + i as u16 * 3;
+        if y + 3 > area.bottom() {
+            break;
+        }
+        let toast_area = Rect::new(area.right().saturating_sub(width + 1), y, width, 3);
+        let (_, color) = category_label(n.error.category, theme);
+        let toast = Paragraph::new(notification_line(n, app)).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(color))
+                .style(Style::default().bg(theme.sub_bg)),
+        );
+
+        frame.render_widget(Clear, toast_area);
+        frame.render_widget(toast, toast_area);
+    }
+}
+
+fn popup_area(area: Rect, width_percent: u16, height_percent: u16) -> Rect {
+    let width = area.width * width_percent / 100;
+    let height = area.height * height_percent / 100;
+    Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    )
+}
+
+fn draw_error_log(frame: &mut Frame, area: Rect, app: &AppState) {
+    let theme = &app.theme;
+    let area = popup_area(area, 80, 60);
+    let visible_rows = area.height.saturating_sub(2) as usize;
+    let end = app
+        .notifications
+        .len()
+        .saturating_sub(app.error_log.scroll_offset);
+    let start = end.saturating_sub(visible_rows);
+    let items: Vec<ListItem> = app
+        .notifications
+        .range(start..end)
+        .map(|n| ListItem::new(notification_line(n, app)))
+        .collect();
+
+    let title = Line::from(vec![
+        Span::styled("[ ", Style::default().fg(theme.text_muted)),
+        Span::styled("Errors", Style::default().fg(theme.border)),
+        Span::styled(
+            format!(
+                ": {} ] - [Up/Down] scroll - [e/ESC] close",
+                app.notifications.len()
+            ),
+            Style::default().fg(theme.text_muted),
+        ),
+    ]);
+    let log = List::new(items).block(
+        Block::default()
+            .title(title)
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(theme.border))
+            .style(Style::default().bg(theme.sub_bg)),
+    );
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(log, area);
+}
+
+struct AuthorAreas {
+    popup: Rect,
+    header: Rect,
+    heading: Rect,
+    messages: Rect,
+}
+
+fn author_areas(area: Rect) -> AuthorAreas {
+    let popup = popup_area(area, 80, 80);
+    let inner = Block::default().borders(Borders::ALL).inner(popup);
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(AUTHOR_HEADER_ROWS),
+            Constraint::Length(1),
+            Constraint::Min(0),
+        ])
+        .split(inner);
+    AuthorAreas {
+        popup,
+        header: rows[0],
+        heading: rows[1],
+        messages: rows[2],
+    }
+}
+
+/// The author's messages, oldest first, wrapped to `width`.
+fn author_message_lines(
+    details: &AuthorDetails,
+    width: usize,
+    app: &AppState,
+) -> Vec<Line<'static>> {
+    let theme = &app.theme;
+    let mut lines = Vec::new();
+    for m in &details.messages {
+        let prefix = format!("[{}] ", app.time_format.date_time(m.timestamp));
+        let indent = " ".repeat(prefix.chars().count());
+        let body_width = width.saturating_sub(indent.len()).max(1);
+        // the chat shows paid and membership events as banners, here they lead the text
+        let lead = match &m.kind {
+            MessageKind::SuperChat { amount, .. } | MessageKind::SuperSticker { amount, .. } => {
+                amount.clone()
             }
+            kind => membership_headline(kind),
+        };
+        let text = format!("{} {}", lead, m.message);
+        let wrapped = textwrap::wrap(text.trim(), body_width);
+        let mut first = vec![Span::styled(prefix, Style::default().fg(theme.text_muted))];
+        if app.show_stream_badges {
+            first.push(Span::styled(
+                STREAM_BADGE,
+                Style::default().fg(stream_color(m.stream, theme)),
+            ));
+        }
+        let mut parts = wrapped.iter();
+        first.push(Span::styled(
+            parts.next().map(|p| p.to_string()).unwrap_or_default(),
+            body_style(m, theme),
+        ));
+        lines.push(Line::from(first));
+        lines.extend(parts.map(|part| {
+            Line::from(vec![
+                Span::raw(indent.clone()),
+                Span::styled(part.to_string(), body_style(m, theme)),
+            ])
+        }));
+    }
+    lines
+}
+
+/// How far the author popup's message list scrolls back inside the view `area`.
+pub fn author_max_scroll(area: Rect, app: &AppState) -> usize {
+    let Some(details) = &app.author else {
+        return 0;
+    };
+    let messages = author_areas(area).messages;
+    author_message_lines(details, messages.width as usize, app)
+        .len()
+        .saturating_sub(messages.height as usize)
+}
+
+fn author_header_lines(details: &AuthorDetails, app: &AppState) -> Vec<Line<'static>> {
+    let theme = &app.theme;
+    let m = &details.author;
+    let field = |label: &str, value: String| {
+        Line::from(vec![
+            Span::styled(
+                format!("{:<12}", label),
+                Style::default().fg(theme.text_muted),
+            ),
+            Span::styled(value, Style::default().fg(theme.text)),
+        ])
+    };
+
+    let mut name = badge_spans(m, app);
+    name.push(Span::styled(m.author.clone(), author_style(m, theme)));
+    let mut roles: Vec<String> = [
+        (m.is_owner, "owner"),
+        (m.is_moderator, "moderator"),
+        (m.is_verified, "verified"),
+    ]
+    .into_iter()
+    .filter(|(has_role, _)| *has_role)
+    .map(|(_, role)| role.to_string())
+    .collect();
+    if m.is_member {
+        roles.push(match &m.member_level {
+            Some(level) => format!("member ({})", level),
+            None => "member".to_string(),
+        });
+    }
+    if roles.is_empty() {
+        roles.push("viewer".to_string());
+    }
+    let mut count = format!("{} this session", details.session_count);
+    if let Some(archived) = details.archived_count {
+        count.push_str(&format!(", {} archived", archived));
+    }
+
+    vec![
+        Line::from(name),
+        field("Channel", m.author_channel_id.clone()),
+        field(
+            "URL",
+            format!("https://www.youtube.com/channel/{}", m.author_channel_id),
+        ),
+        field("Roles", roles.join(", ")),
+        field(
+            "First seen",
+            details
+                .first_seen
+                .map_or_else(|| "-".to_string(), |at| app.time_format.date_time(at)),
+        ),
+        field("Messages", count),
+    ]
+}
+
+fn draw_author(frame: &mut Frame, area: Rect, app: &AppState, details: &AuthorDetails) {
+    let theme = &app.theme;
+    let areas = author_areas(area);
+
+    let title = Line::from(vec![
+        Span::styled("[ ", Style::default().fg(theme.text_muted)),
+        Span::styled("Author", Style::default().fg(theme.border)),
+        Span::styled(
+            format!(
+                ": {} ] - [Up/Down] scroll - [ESC/Enter] close",
+                details.author.author
+            ),
+            Style::default().fg(theme.text_muted),
+        ),
+    ]);
+    let block = Block::default()
+        .title(title)
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme.border))
+        .style(Style::default().bg(theme.sub_bg));
+    frame.render_widget(Clear, areas.popup);
+    frame.render_widget(block, areas.popup);
+
+    let mut header = areas.header;
+    if let Some(avatar) = &details.author.avatar {
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([
+                Constraint::Length(LARGE_AVATAR_COLS + 2),
+                Constraint::Min(1),
+            ])
+            .split(header);
+        frame.render_widget(Paragraph::new(large_avatar_lines(avatar)), columns[0]);
+        header = columns[1];
+    }
+    frame.render_widget(
+        Paragraph::new(author_header_lines(details, app)).wrap(Wrap { trim: false }),
+        header,
+    );
+
+    frame.render_widget(
+        Paragraph::new(Span::styled(
+            format!("Recent messages ({})", details.messages.len()),
+            Style::default().fg(theme.border),
+        )),
+        areas.heading,
+    );
+
+    let lines = author_message_lines(details, areas.messages.width as usize, app);
+    let visible_rows = areas.messages.height as usize;
+    let end = lines.len().saturating_sub(details.scroll_offset);
+    let start = end.saturating_sub(visible_rows);
+    frame.render_widget(Paragraph::new(lines[start..end].to_vec()), areas.messages);
+}
+
+/// Tab bar listing the watched streams, also the color legend for stream badges.
+pub fn draw_tabs(
+    frame: &mut Frame,
+    area: Rect,
+    titles: &[&str],
+    focused: StreamId,
+    app: &AppState,
+) {
+    let theme = &app.theme;
+    let mut spans = Vec::with_capacity(titles.len() * 2);
+    for (i, title) in titles.iter().enumerate() {
+        let style = if i == focused {
+            Style::default()
+                .fg(theme.text)
+                .bg(theme.selected)
+                .add_modifier(Modifier::BOLD)
+        } else {
+            Style::default().fg(theme.text_muted)
+        };
+        spans.push(Span::styled(
+            STREAM_BADGE,
+            Style::default().fg(stream_color(i, theme)),
+        ));
+        spans.push(Span::styled(format!("{} ", title), style));
+    }
+    spans.push(Span::styled(
+        format!(
+            " [{}] switch - [{}] layout",
+            app.keymap.label(KeyAction::NextStream),
+            app.keymap.label(KeyAction::CycleLayout),
+        ),
+        Style::default().fg(theme.text_muted),
+    ));
+
+    frame.render_widget(
+        Paragraph::new(Line::from(spans)).style(Style::default().bg(theme.bg)),
+        area,
+    );
+}
+
+/// Draws one chat view into `area`, `focused` views get the highlighted border.
+pub fn draw(frame: &mut Frame, area: Rect, app: &AppState, focused: bool) {
+    let theme = &app.theme;
+    let areas = layout(area, app);
+
+    if let (Some(area), Some(poll)) = (areas.poll, &app.poll) {
+        draw_poll(frame, area, poll, app.poll_collapsed, theme);
+    }
+
+    let visible_rows = areas.chat.height.saturating_sub(2) as usize;
+    let chat_width = areas.chat.width.saturating_sub(2) as usize;
+
+    let all_rows: Vec<ListItem> = app
+        .messages
+        .iter()
+        .enumerate()
+        .filter(|(_, m)| app.filter.matches(m))
+        .flat_map(|(i, m)| {
+            let selected = app.selected == Some(i);
+            message_rows(app, m, chat_width)
+                .into_iter()
+                .map(move |row| {
+                    let item = ListItem::new(row);
+                    if selected {
+                        item.style(Style::default().bg(theme.selected))
+                    } else {
+                        item
+                    }
+                })
         })
         .collect();
 
@@ -196,27 +1137,153 @@ pub fn draw(frame: &mut Frame, app: &AppState) {
             Block::default()
                 .title(build_title(app))
                 .borders(Borders::ALL)
-                .border_style(Style::default().fg(COLOR_BORDER))
-                .style(Style::default().bg(COLOR_BG)),
+                .border_style(Style::default().fg(if focused {
+                    theme.border
+                } else {
+                    theme.text_muted
+                }))
+                .style(Style::default().bg(theme.bg)),
         )
-        .style(Style::default().bg(COLOR_BG));
+        .style(Style::default().bg(theme.bg));
 
-    let scroll_mode = if app.scroll_state.auto_scroll == true {
+    let scroll_mode = if app.scroll_state.auto_scroll {
         "[FOLLOWING LIVE CHAT]"
     } else {
         "[FOLLOW DISABLED]"
     };
 
-    let help = Paragraph::new(Line::from(vec![Span::styled(
+    let keys = &app.keymap;
+    let help_text = if app.selected.is_some() {
+        let back = [String::from("ESC"), keys.label(KeyAction::Select)];
         format!(
-            "{} - [Up/Down/PgUp/PgDn/Home/End] scroll - [ESC/q] quit",
-            scroll_mode
-        ),
-        Style::default().fg(Color::Rgb(106, 112, 128)),
-    )]))
-    .style(Style::default().bg(COLOR_BG))
+            " [SELECT] - [{}/{}] move - [{}] author - [{}] only this author - [{}] delete - [{}] timeout - [{}] ban - [{}] back",
+            keys.label(KeyAction::ScrollUp),
+            keys.label(KeyAction::ScrollDown),
+            keys.label(KeyAction::ShowAuthor),
+            keys.label(KeyAction::FilterAuthor),
+            keys.label(KeyAction::Delete),
+            keys.label(KeyAction::Timeout),
+            keys.label(KeyAction::Ban),
+            back.join("/"),
+        )
+    } else if let Some(search) = app.search.as_ref().filter(|s| !s.editing) {
+        let matches = app.search_matches();
+        let position = search
+            .current
+            .and_then(|current| matches.iter().position(|&i| i == current))
+            .map_or(0, |p| p + 1);
+        format!(
+            " [SEARCH \"{}\" {}/{}] - [{}] older - [{}] newer - [{}] new search - [ESC] clear",
+            search.query,
+            position,
+            matches.len(),
+            keys.label(KeyAction::NextMatch),
+            keys.label(KeyAction::PrevMatch),
+            keys.label(KeyAction::Search),
+        )
+    } else {
+        let scroll_keys: Vec<String> = [
+            KeyAction::ScrollUp,
+            KeyAction::ScrollDown,
+            KeyAction::PageUp,
+            KeyAction::PageDown,
+            KeyAction::Top,
+            KeyAction::Bottom,
+        ]
+        .into_iter()
+        .map(|action| keys.label(action))
+        .filter(|label| !label.is_empty())
+        .collect();
+        format!(
+            " {} - [{}] scroll{} - [{}] select - [{}] search - [{}] filter - [{}] poll - [{}] time - [{}] errors - [{}] quit",
+            scroll_mode,
+            scroll_keys.join("/"),
+            if app.write_enabled {
+                format!(" - [{}] chat", keys.label(KeyAction::Compose))
+            } else {
+                String::new()
+            },
+            keys.label(KeyAction::Select),
+            keys.label(KeyAction::Search),
+            keys.label(KeyAction::CycleFilter),
+            keys.label(KeyAction::TogglePoll),
+            keys.label(KeyAction::CycleTimeStyle),
+            keys.label(KeyAction::ErrorLog),
+            keys.label(KeyAction::Quit),
+        )
+    };
+    let help = Paragraph::new(Line::from(vec![
+        build_status(&app.status, theme),
+        Span::styled(help_text, Style::default().fg(theme.help)),
+    ]))
+    .style(Style::default().bg(theme.bg))
     .wrap(Wrap { trim: true });
 
-    frame.render_widget(chat, areas[0]);
-    frame.render_widget(help, areas[1]);
+    frame.render_widget(chat, areas.chat);
+    if let (Some(area), Some(banner)) = (areas.banner, build_banner(app)) {
+        frame.render_widget(
+            Paragraph::new(banner).style(Style::default().bg(theme.bg)),
+            area,
+        );
+    }
+    frame.render_widget(help, areas.help);
+    if let (Some(area), Some(text)) = (areas.input, &app.input) {
+        draw_input(frame, area, text, theme);
+    }
+    if let (Some(area), Some(search)) = (areas.input, &app.search)
+        && search.editing
+    {
+        draw_search(frame, area, app, search);
+    }
+
+    if let Some(dialog) = &app.dialog {
+        draw_dialog(frame, area, dialog, theme);
+    }
+    if let Some(details) = &app.author {
+        draw_author(frame, area, app, details);
+    }
+    if app.error_log.visible {
+        draw_error_log(frame, area, app);
+    } else {
+        draw_toasts(frame, areas.chat, app);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::time::{TimeFormat, TimeStyle, Zone};
+    use crate::config::Config;
+
+    fn state() -> AppState {
+        let time_format = TimeFormat {
+            zone: Zone::Local,
+            hour12: false,
+            style: TimeStyle::Clock,
+        };
+        AppState::new("test".to_string(), true, time_format, &Config::default())
+    }
+
+    #[test]
+    fn filtered_view_scrolls_over_matching_rows_only() {
+        let mut state = state();
+        for i in 0..10 {
+            state.push_local_echo(0, format!("local-{}", i), "hi".to_string());
+        }
+        // a long message from someone the filter hides wraps over several rows
+        state.push_local_echo(0, "local-long".to_string(), "word ".repeat(100));
+        for m in state.messages.iter_mut().take(4) {
+            m.is_member = true;
+        }
+        let (width, rows) = (60, 3);
+        let all = max_scroll_for_viewport(&state, width, rows);
+        assert!(all > 11 - rows);
+
+        state.filter = Filter::Members;
+        assert_eq!(max_scroll_for_viewport(&state, width, rows), 4 - rows);
+        assert_eq!(max_scroll_for_viewport(&state, width, 10), 0);
+
+        state.filter = Filter::Verified;
+        assert_eq!(max_scroll_for_viewport(&state, width, rows), 0);
+    }
 }
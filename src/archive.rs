@@ -0,0 +1,245 @@
+use crate::youtube_api_v3::LiveChatMessage;
+use anyhow::Context;
+use log::debug;
+use prost::Message;
+use rusqlite::{Connection, OptionalExtension, params, params_from_iter};
+use std::path::Path;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+pub const ARCHIVE_FILE: &str = "archive.sqlite3";
+/// How long a query waits for the other connection to let go of the database.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Schema versions, applied in order on open. Never edit a shipped entry, append a new one.
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE messages (
+        seq INTEGER PRIMARY KEY AUTOINCREMENT,
+        id TEXT NOT NULL UNIQUE,
+        video_id TEXT NOT NULL,
+        author_channel_id TEXT,
+        type TEXT NOT NULL,
+        published_at TEXT,
+        raw BLOB NOT NULL
+    );
+    CREATE INDEX messages_video_id_seq ON messages (video_id, seq);",
+    "CREATE INDEX messages_author_seq ON messages (author_channel_id, seq);",
+];
+
+struct ArchiveRecord {
+    video_id: String,
+    item: LiveChatMessage,
+}
+
+/// Handle given to a stream's chat task, every item it stores is keyed by `video_id`.
+#[derive(Clone)]
+pub struct ArchiveSink {
+    video_id: String,
+    tx: mpsc::UnboundedSender<ArchiveRecord>,
+}
+
+impl ArchiveSink {
+    pub fn store(&self, item: &LiveChatMessage) {
+        let _ = self.tx.send(ArchiveRecord {
+            video_id: self.video_id.clone(),
+            item: item.clone(),
+        });
+    }
+}
+
+/// An archived item, oldest first when returned from `Archive::older_than`.
+pub struct ArchivedMessage {
+    pub video_id: String,
+    pub item: LiveChatMessage,
+}
+
+/// What the archive holds of one author, across every archived video.
+pub struct AuthorHistory {
+    pub count: usize,
+    /// The author's first archived item.
+    pub first: Option<ArchivedMessage>,
+    /// Their newest items, oldest first.
+    pub recent: Vec<ArchivedMessage>,
+}
+
+pub struct Archive {
+    conn: Connection,
+}
+
+impl Archive {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let mut conn = Connection::open(path)
+            .with_context(|| format!("Failed to open archive {}", path.display()))?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        // the app reads while the writer thread holds its own connection
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        migrate(&mut conn, "archive", MIGRATIONS)?;
+
+        Ok(Self { conn })
+    }
+
+    /// Up to `limit` items of the given videos archived before the message `before_id`.
+    pub fn older_than(
+        &self,
+        video_ids: &[&str],
+        before_id: &str,
+        limit: usize,
+    ) -> anyhow::Result<Vec<ArchivedMessage>> {
+        let Some(before_seq) = self
+            .conn
+            .query_row(
+                "SELECT seq FROM messages WHERE id = ?1",
+                [before_id],
+                |row| row.get::<_, i64>(0),
+            )
+            .optional()?
+        else {
+            return Ok(Vec::new());
+        };
+
+        let placeholders = vec!["?"; video_ids.len()].join(", ");
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT video_id, raw FROM messages
+             WHERE seq < ? AND video_id IN ({})
+             ORDER BY seq DESC LIMIT {}",
+            placeholders, limit
+        ))?;
+        let args = std::iter::once(before_seq.to_string())
+            .chain(video_ids.iter().map(|id| id.to_string()));
+        let mut messages = stmt
+            .query_map(params_from_iter(args), read_row)?
+            .map(decode)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        messages.reverse();
+
+        Ok(messages)
+    }
+
+    /// The number of items archived from `channel_id`, the first one and up to `limit` of the newest.
+    pub fn author_history(&self, channel_id: &str, limit: usize) -> anyhow::Result<AuthorHistory> {
+        let count = self.conn.query_row(
+            "SELECT COUNT(*) FROM messages WHERE author_channel_id = ?1",
+            [channel_id],
+            |row| row.get::<_, i64>(0),
+        )?;
+        let first = self
+            .conn
+            .query_row(
+                "SELECT video_id, raw FROM messages
+                 WHERE author_channel_id = ?1 ORDER BY seq LIMIT 1",
+                [channel_id],
+                read_row,
+            )
+            .optional()?
+            .map(|row| decode(Ok(row)))
+            .transpose()?;
+
+        let mut stmt = self.conn.prepare(
+            "SELECT video_id, raw FROM messages
+             WHERE author_channel_id = ?1 ORDER BY seq DESC LIMIT ?2",
+        )?;
+        let mut recent = stmt
+            .query_map(params![channel_id, limit as i64], read_row)?
+            .map(decode)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        recent.reverse();
+
+        Ok(AuthorHistory {
+            count: count as usize,
+            first,
+            recent,
+        })
+    }
+
+    fn insert(&self, record: &ArchiveRecord) -> anyhow::Result<()> {
+        let Some(id) = record.item.id.as_deref() else {
+            return Ok(());
+        };
+        let snippet = record.item.snippet.as_ref();
+        self.conn.execute(
+            "INSERT OR IGNORE INTO messages (id, video_id, author_channel_id, type, published_at, raw)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                id,
+                record.video_id,
+                record
+                    .item
+                    .author_details
+                    .as_ref()
+                    .and_then(|d| d.channel_id.as_deref()),
+                snippet
+                    .map(|s| s.r#type().as_str_name())
+                    .unwrap_or_default(),
+                snippet.and_then(|s| s.published_at.as_deref()),
+                record.item.encode_to_vec(),
+            ],
+        )?;
+
+        Ok(())
+    }
+}
+
+fn read_row(row: &rusqlite::Row) -> rusqlite::Result<(String, Vec<u8>)> {
+    Ok((row.get(0)?, row.get(1)?))
+}
+
+fn decode(row: rusqlite::Result<(String, Vec<u8>)>) -> anyhow::Result<ArchivedMessage> {
+    let (video_id, raw) = row?;
+    Ok(ArchivedMessage {
+        video_id,
+        item: LiveChatMessage::decode(raw.as_slice())?,
+    })
+}
+
+/// Brings the database `name` up to the last of `migrations`, its version is kept in `user_version`.
+pub fn migrate(conn: &mut Connection, name: &str, migrations: &[&str]) -> anyhow::Result<()> {
+    let version: i64 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    let version = version as usize;
+    if version > migrations.len() {
+        anyhow::bail!(
+            "{} schema version {} is newer than this build supports",
+            name,
+            version
+        );
+    }
+
+    for (i, migration) in migrations.iter().enumerate().skip(version) {
+        debug!("migrating {} to schema version {}", name, i + 1);
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", (i + 1) as i64)?;
+        tx.commit()?;
+    }
+
+    Ok(())
+}
+
+/// Sending end of the archive writer thread.
+pub struct ArchiveWriter {
+    tx: mpsc::UnboundedSender<ArchiveRecord>,
+}
+
+impl ArchiveWriter {
+    pub fn sink(&self, video_id: &str) -> ArchiveSink {
+        ArchiveSink {
+            video_id: video_id.to_string(),
+            tx: self.tx.clone(),
+        }
+    }
+}
+
+/// Opens the archive at `path` and moves it to a writer thread, sqlite calls block.
+pub fn spawn_archive_task(path: &Path) -> anyhow::Result<ArchiveWriter> {
+    let archive = Archive::open(path)?;
+    let (tx, mut rx) = mpsc::unbounded_channel::<ArchiveRecord>();
+
+    std::thread::spawn(move || {
+        while let Some(record) = rx.blocking_recv() {
+            if let Err(e) = archive.insert(&record) {
+                debug!("archiving message failed: {:#}", e);
+            }
+        }
+    });
+
+    Ok(ArchiveWriter { tx })
+}
@@ -0,0 +1,397 @@
+use crate::app::event::Avatar;
+use crate::archive::migrate;
+use crate::config::AvatarConfig;
+use anyhow::Context;
+use chrono::Utc;
+use image::RgbaImage;
+use log::debug;
+use rusqlite::{Connection, OptionalExtension, params};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+pub const AVATAR_DIR: &str = "avatars";
+const INDEX_FILE: &str = "index.sqlite3";
+/// Image ids are 24 bits, what a kitty placeholder's foreground color carries.
+const MAX_IMAGE_ID: u32 = 0x00FF_FFFF;
+
+/// Schema versions, applied in order on open. Never edit a shipped entry, append a new one.
+const MIGRATIONS: &[&str] = &["CREATE TABLE images (
+        digest TEXT PRIMARY KEY,
+        id INTEGER NOT NULL UNIQUE,
+        width INTEGER NOT NULL,
+        height INTEGER NOT NULL,
+        bytes INTEGER NOT NULL,
+        last_used INTEGER NOT NULL
+    );
+    CREATE INDEX images_last_used ON images (last_used);
+    CREATE TABLE urls (
+        url TEXT PRIMARY KEY,
+        digest TEXT NOT NULL REFERENCES images (digest) ON DELETE CASCADE,
+        fetched_at INTEGER NOT NULL
+    );
+    CREATE INDEX urls_digest ON urls (digest);"];
+
+/// Resized avatars kept in the app directory across sessions. Files are named by the
+/// SHA-256 of their pixels, so authors sharing a picture share a file, and an sqlite
+/// index maps profile image URLs to them.
+pub struct AvatarCache {
+    dir: PathBuf,
+    conn: Connection,
+    /// URLs fetched longer ago are fetched again, pictures unused as long are dropped.
+    ttl: Duration,
+    max_bytes: u64,
+    /// Size avatars are fetched in, pictures cached in another size don't count.
+    pixels: u32,
+    cols: u16,
+    /// Avatars handed out in this session, so every message of an author shares one.
+    /// Once nothing holds an avatar anymore its picture can be evicted again.
+    loaded: HashMap<String, Weak<Avatar>>,
+}
+
+impl AvatarCache {
+    pub fn open(dir: &Path, config: &AvatarConfig) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+        let path = dir.join(INDEX_FILE);
+        let mut conn = Connection::open(&path)
+            .with_context(|| format!("Failed to open avatar cache {}", path.display()))?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "foreign_keys", true)?;
+        conn.busy_timeout(Duration::from_secs(2))?;
+        migrate(&mut conn, "avatar cache", MIGRATIONS)?;
+
+        let mut cache = Self {
+            dir: dir.to_path_buf(),
+            conn,
+            ttl: Duration::from_secs(config.cache_ttl_days * 86_400),
+            max_bytes: config.cache_max_mb * 1024 * 1024,
+            pixels: config.pixels,
+            cols: config.cols,
+            loaded: HashMap::new(),
+        };
+        cache.evict()?;
+        Ok(cache)
+    }
+
+    /// The avatar at `url` if it was fetched within the TTL and its file is still there.
+    pub fn get(&mut self, url: &str) -> anyhow::Result<Option<Arc<Avatar>>> {
+        if let Some(avatar) = self.loaded.get(url).and_then(Weak::upgrade) {
+            return Ok(Some(avatar));
+        }
+
+        let fresh_since = now() - self.ttl.as_secs() as i64;
+        let Some((digest, id, width, height)) = self
+            .conn
+            .query_row(
+                "SELECT images.digest, id, width, height FROM urls
+                 JOIN images ON images.digest = urls.digest
+                 WHERE url = ?1 AND fetched_at >= ?2 AND width = ?3 AND height = ?3",
+                params![url, fresh_since, self.pixels],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get(1)?,
+                        row.get(2)?,
+                        row.get(3)?,
+                    ))
+                },
+            )
+            .optional()?
+        else {
+            return Ok(None);
+        };
+        let path = self.file(&digest);
+        if !path.exists() {
+            debug!("avatar file missing digest={}", digest);
+            self.conn
+                .execute("DELETE FROM images WHERE digest = ?1", [&digest])?;
+            return Ok(None);
+        }
+        self.conn.execute(
+            "UPDATE images SET last_used = ?1 WHERE digest = ?2",
+            params![now(), digest],
+        )?;
+
+        Ok(Some(self.hand_out(url, id, width, height, &path)))
+    }
+
+    /// Stores the avatar fetched from `url`, reusing the file if another URL had the same picture.
+    pub fn insert(&mut self, url: &str, image: &RgbaImage) -> anyhow::Result<Arc<Avatar>> {
+        let (width, height) = image.dimensions();
+        let mut hasher = Sha256::new();
+        hasher.update(width.to_le_bytes());
+        hasher.update(height.to_le_bytes());
+        hasher.update(image.as_raw());
+        let digest: String = hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+
+        let path = self.file(&digest);
+        let existing = self
+            .conn
+            .query_row(
+                "SELECT id FROM images WHERE digest = ?1",
+                [&digest],
+                |row| row.get::<_, u32>(0),
+            )
+            .optional()?;
+        let id = match existing {
+            Some(id) if path.exists() => id,
+            existing => {
+                std::fs::write(&path, image.as_raw())
+                    .with_context(|| format!("Failed to write {}", path.display()))?;
+                match existing {
+                    Some(id) => id,
+                    None => self.new_image(&digest, width, height, image.as_raw().len())?,
+                }
+            }
+        };
+        self.conn.execute(
+            "UPDATE images SET last_used = ?1 WHERE digest = ?2",
+            params![now(), digest],
+        )?;
+        self.conn.execute(
+            "INSERT OR REPLACE INTO urls (url, digest, fetched_at) VALUES (?1, ?2, ?3)",
+            params![url, digest, now()],
+        )?;
+        // handed out first so eviction keeps it
+        let avatar = self.hand_out(url, id, width, height, &path);
+        self.evict()?;
+        Ok(avatar)
+    }
+
+    /// Indexes a new picture under an id no other picture has. The id starts from the
+    /// digest, so a picture keeps its id for as long as it stays cached.
+    fn new_image(
+        &self,
+        digest: &str,
+        width: u32,
+        height: u32,
+        bytes: usize,
+    ) -> anyhow::Result<u32> {
+        let start = u32::from_str_radix(&digest[..6], 16)?;
+        let mut id = start.max(1);
+        loop {
+            let taken = self
+                .conn
+                .query_row("SELECT 1 FROM images WHERE id = ?1", [id], |_| Ok(()))
+                .optional()?
+                .is_some();
+            if !taken {
+                break;
+            }
+            id = id % MAX_IMAGE_ID + 1;
+            if id == start.max(1) {
+                anyhow::bail!("no free avatar image id");
+            }
+        }
+
+        self.conn.execute(
+            "INSERT INTO images (digest, id, width, height, bytes, last_used)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![digest, id, width, height, bytes as i64, now()],
+        )?;
+        Ok(id)
+    }
+
+    fn hand_out(
+        &mut self,
+        url: &str,
+        id: u32,
+        width: u32,
+        height: u32,
+        path: &Path,
+    ) -> Arc<Avatar> {
+        let avatar = Arc::new(Avatar {
+            id,
+            cols: self.cols,
+            width,
+            height,
+            path: path.to_string_lossy().into_owned(),
+        });
+        self.loaded.insert(url.to_string(), Arc::downgrade(&avatar));
+        avatar
+    }
+
+    /// Width and height avatars should be scaled to before they're inserted.
+    pub fn pixels(&self) -> u32 {
+        self.pixels
+    }
+
+    fn file(&self, digest: &str) -> PathBuf {
+        self.dir.join(format!("{}.rgba", digest))
+    }
+
+    /// Drops pictures unused for longer than the TTL, then the least recently used ones
+    /// until the cache fits `max_bytes`. Pictures still held by this session are kept.
+    fn evict(&mut self) -> anyhow::Result<()> {
+        self.loaded.retain(|_, avatar| avatar.strong_count() > 0);
+        let in_use: Vec<u32> = self
+            .loaded
+            .values()
+            .filter_map(Weak::upgrade)
+            .map(|a| a.id)
+            .collect();
+        let mut stmt = self
+            .conn
+            .prepare("SELECT digest, id, bytes, last_used FROM images ORDER BY last_used")?;
+        let images = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, u32>(1)?,
+                    row.get::<_, i64>(2)? as u64,
+                    row.get::<_, i64>(3)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        drop(stmt);
+
+        let expired_before = now() - self.ttl.as_secs() as i64;
+        let mut total: u64 = images.iter().map(|(_, _, bytes, _)| bytes).sum();
+        for (digest, id, bytes, last_used) in images {
+            if total <= self.max_bytes && last_used >= expired_before {
+                break;
+            }
+            if in_use.contains(&id) {
+                continue;
+            }
+            debug!("evicting avatar digest={}", digest);
+            self.conn
+                .execute("DELETE FROM images WHERE digest = ?1", [&digest])?;
+            if let Err(e) = std::fs::remove_file(self.file(&digest))
+                && e.kind() != std::io::ErrorKind::NotFound
+            {
+                debug!("removing avatar file failed digest={} err={}", digest, e);
+            }
+            total -= bytes;
+        }
+
+        Ok(())
+    }
+}
+
+fn now() -> i64 {
+    Utc::now().timestamp()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    const PIXELS: u32 = 2;
+    /// Bytes of one `PIXELS` square picture.
+    const IMAGE_BYTES: u64 = (PIXELS * PIXELS * 4) as u64;
+
+    fn cache(name: &str) -> AvatarCache {
+        let dir = std::env::temp_dir().join(format!("ytc-avatars-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let config = AvatarConfig {
+            pixels: PIXELS,
+            ..AvatarConfig::default()
+        };
+        AvatarCache::open(&dir, &config).unwrap()
+    }
+
+    fn image(shade: u8) -> RgbaImage {
+        RgbaImage::from_pixel(PIXELS, PIXELS, Rgba([shade, 0, 0, 255]))
+    }
+
+    fn set_last_used(cache: &AvatarCache, avatar: &Avatar, last_used: i64) {
+        cache
+            .conn
+            .execute(
+                "UPDATE images SET last_used = ?1 WHERE id = ?2",
+                params![last_used, avatar.id],
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn eviction_drops_least_recently_used_released_pictures() {
+        let mut cache = cache("lru");
+        cache.max_bytes = 3 * IMAGE_BYTES;
+        let a = cache.insert("a", &image(1)).unwrap();
+        let b = cache.insert("b", &image(2)).unwrap();
+        let held = cache.insert("held", &image(3)).unwrap();
+        set_last_used(&cache, &held, now() - 30);
+        set_last_used(&cache, &a, now() - 20);
+        set_last_used(&cache, &b, now() - 10);
+        let (a_path, b_path) = (a.path.clone(), b.path.clone());
+        drop((a, b));
+
+        // the held picture is the oldest but still drawn, the released `a` goes instead
+        let c = cache.insert("c", &image(4)).unwrap();
+        assert!(!Path::new(&a_path).exists());
+        assert!(Path::new(&b_path).exists());
+        assert!(Path::new(&held.path).exists());
+        assert!(Path::new(&c.path).exists());
+        assert!(cache.get("a").unwrap().is_none());
+        assert!(!cache.loaded.contains_key("a"));
+
+        let _ = std::fs::remove_dir_all(&cache.dir);
+    }
+
+    #[test]
+    fn urls_fetched_before_the_ttl_are_fetched_again() {
+        let mut cache = cache("ttl");
+        let avatar = cache.insert("a", &image(1)).unwrap();
+        assert_eq!(cache.get("a").unwrap().map(|a| a.id), Some(avatar.id));
+
+        let expired = now() - cache.ttl.as_secs() as i64 - 1;
+        cache
+            .conn
+            .execute("UPDATE urls SET fetched_at = ?1", [expired])
+            .unwrap();
+        // still held, so every message of the author keeps sharing it
+        assert!(cache.get("a").unwrap().is_some());
+        let path = avatar.path.clone();
+        drop(avatar);
+        assert!(cache.get("a").unwrap().is_none());
+
+        // unused as long, the picture itself goes on the next eviction
+        cache
+            .conn
+            .execute("UPDATE images SET last_used = ?1", [expired])
+            .unwrap();
+        cache.evict().unwrap();
+        assert!(!Path::new(&path).exists());
+
+        let _ = std::fs::remove_dir_all(&cache.dir);
+    }
+
+    #[test]
+    fn pictures_share_files_and_probe_for_free_ids() {
+        let mut cache = cache("ids");
+        let a = cache.insert("a", &image(1)).unwrap();
+        let same = cache.insert("same picture", &image(1)).unwrap();
+        assert_eq!((a.id, &a.path), (same.id, &same.path));
+
+        let digest = |id: u32| format!("{:06x}{}", id, "0".repeat(58));
+        let taken = cache.new_image(&digest(a.id), PIXELS, PIXELS, 16).unwrap();
+        assert_eq!(taken, a.id % MAX_IMAGE_ID + 1);
+
+        // the last id wraps around to 1, 0 isn't a valid id
+        let last = cache
+            .new_image(&digest(MAX_IMAGE_ID), PIXELS, PIXELS, 16)
+            .unwrap();
+        assert_eq!(last, MAX_IMAGE_ID);
+        let wrapped = cache
+            .new_image(
+                &format!("{}1", &digest(MAX_IMAGE_ID)[..63]),
+                PIXELS,
+                PIXELS,
+                16,
+            )
+            .unwrap();
+        assert_eq!(wrapped, 1);
+
+        let _ = std::fs::remove_dir_all(&cache.dir);
+    }
+}
@@ -0,0 +1,198 @@
+use crate::app::event::{AppEvent, Avatar};
+use crate::avatar_cache::AvatarCache;
+use image::RgbaImage;
+use image::imageops::FilterType;
+use log::{debug, warn};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, Weak};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+
+/// Workers loading avatars, so this many downloads run at once.
+const WORKERS: usize = 4;
+/// URLs waiting for a worker. When it's full new URLs are dropped and asked for
+/// again with the author's next message.
+const QUEUE_LEN: usize = 256;
+/// How long a URL that failed to load is left alone before it's tried again.
+const RETRY_FAILED_AFTER: Duration = Duration::from_secs(5 * 60);
+
+/// Fetches avatars off the chat path. Avatars already loaded in this session are handed
+/// out right away, the others are looked up on disk or downloaded in the background and
+/// announced with `AppEvent::AvatarReady`.
+#[derive(Clone)]
+pub struct AvatarFetcher {
+    state: Arc<Mutex<FetchState>>,
+    queue: mpsc::Sender<String>,
+}
+
+/// What the chat path looks at, kept in memory so it never waits on the disk.
+struct FetchState {
+    /// Avatars loaded in this session by URL, for as long as something still holds them.
+    loaded: HashMap<String, Weak<Avatar>>,
+    /// URLs queued or being loaded, with the channel ids of the authors waiting for
+    /// them. A second request for the same URL only joins the waiters.
+    pending: HashMap<String, HashSet<String>>,
+    /// URLs that failed to load and when, see `RETRY_FAILED_AFTER`.
+    failed: HashMap<String, Instant>,
+}
+
+/// Loads queued URLs, shared by the workers.
+struct Loader {
+    http: reqwest::Client,
+    /// Only used on blocking threads, sqlite and file calls can stall.
+    cache: Arc<Mutex<AvatarCache>>,
+    state: Arc<Mutex<FetchState>>,
+    /// Avatars are scaled to this many pixels square before they're cached.
+    pixels: u32,
+    tx: mpsc::Sender<AppEvent>,
+}
+
+impl AvatarFetcher {
+    /// Starts the workers, they stop once every clone of the fetcher is dropped.
+    pub fn new(cache: AvatarCache, tx: mpsc::Sender<AppEvent>) -> anyhow::Result<Self> {
+        let state = Arc::new(Mutex::new(FetchState {
+            loaded: HashMap::new(),
+            pending: HashMap::new(),
+            failed: HashMap::new(),
+        }));
+        let loader = Arc::new(Loader {
+            http: reqwest::Client::builder().build()?,
+            pixels: cache.pixels(),
+            cache: Arc::new(Mutex::new(cache)),
+            state: state.clone(),
+            tx,
+        });
+
+        let (queue, rx) = mpsc::channel::<String>(QUEUE_LEN);
+        let rx = Arc::new(tokio::sync::Mutex::new(rx));
+        for _ in 0..WORKERS {
+            let loader = loader.clone();
+            let rx = rx.clone();
+            tokio::spawn(async move {
+                loop {
+                    let Some(url) = rx.lock().await.recv().await else {
+                        break;
+                    };
+                    let result = loader.load(&url).await;
+                    if !loader.finish(&url, result).await {
+                        break;
+                    }
+                }
+            });
+        }
+
+        Ok(Self { state, queue })
+    }
+
+    /// The avatar at `url` if it was loaded before. Otherwise queues it, unless that
+    /// failed recently or the queue is full, and returns `None`.
+    pub fn get(&self, url: &str, channel_id: &str) -> Option<Arc<Avatar>> {
+        let mut state = lock(&self.state);
+        if let Some(avatar) = state.loaded.get(url).and_then(Weak::upgrade) {
+            return Some(avatar);
+        }
+        if let Some(failed_at) = state.failed.get(url) {
+            if failed_at.elapsed() < RETRY_FAILED_AFTER {
+                return None;
+            }
+            state.failed.remove(url);
+        }
+        if let Some(waiters) = state.pending.get_mut(url) {
+            waiters.insert(channel_id.to_string());
+            return None;
+        }
+
+        match self.queue.try_send(url.to_string()) {
+            Ok(()) => {
+                state
+                    .pending
+                    .insert(url.to_string(), HashSet::from([channel_id.to_string()]));
+            }
+            Err(TrySendError::Full(_)) => debug!("avatar queue full, dropping url={}", url),
+            Err(TrySendError::Closed(_)) => {}
+        }
+        None
+    }
+}
+
+impl Loader {
+    /// The avatar from the disk cache, downloaded and cached on a miss.
+    async fn load(&self, url: &str) -> anyhow::Result<Arc<Avatar>> {
+        let key = url.to_string();
+        let cached = self
+            .with_cache(move |cache| cache.get(&key))
+            .await
+            .unwrap_or_else(|e| {
+                debug!("avatar cache lookup failed url={} err={:#}", url, e);
+                None
+            });
+        if let Some(avatar) = cached {
+            return Ok(avatar);
+        }
+
+        let image = self.download(url).await?;
+        let key = url.to_string();
+        self.with_cache(move |cache| cache.insert(&key, &image))
+            .await
+    }
+
+    async fn download(&self, url: &str) -> anyhow::Result<RgbaImage> {
+        let response = self.http.get(url).send().await?.error_for_status()?;
+        let bytes = response.bytes().await?;
+        let image = image::load_from_memory(&bytes)?;
+        Ok(image
+            .resize_to_fill(self.pixels, self.pixels, FilterType::Lanczos3)
+            .to_rgba8())
+    }
+
+    /// Runs `f` on the disk cache on a blocking thread.
+    async fn with_cache<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut AvatarCache) -> anyhow::Result<T> + Send + 'static,
+    ) -> anyhow::Result<T> {
+        let cache = self.cache.clone();
+        tokio::task::spawn_blocking(move || f(&mut lock(&cache))).await?
+    }
+
+    /// Hands a loaded avatar to everyone who waited for it. A failure is only logged,
+    /// the author's messages go without an avatar. False once the app is gone.
+    async fn finish(&self, url: &str, result: anyhow::Result<Arc<Avatar>>) -> bool {
+        let waiters = {
+            let mut state = lock(&self.state);
+            match &result {
+                Ok(avatar) => {
+                    state.loaded.retain(|_, avatar| avatar.strong_count() > 0);
+                    state.loaded.insert(url.to_string(), Arc::downgrade(avatar));
+                }
+                Err(_) => {
+                    state
+                        .failed
+                        .retain(|_, failed_at| failed_at.elapsed() < RETRY_FAILED_AFTER);
+                    state.failed.insert(url.to_string(), Instant::now());
+                }
+            }
+            state.pending.remove(url).unwrap_or_default()
+        };
+
+        match result {
+            Ok(avatar) => {
+                for channel_id in waiters {
+                    let event = AppEvent::AvatarReady {
+                        channel_id,
+                        avatar: avatar.clone(),
+                    };
+                    if self.tx.send(event).await.is_err() {
+                        return false;
+                    }
+                }
+            }
+            Err(e) => warn!("avatar fetch failed url={} err={:#}", url, e),
+        }
+        true
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
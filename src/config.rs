@@ -0,0 +1,271 @@
+use crate::app::keymap::{KeyAction, KeyMap};
+use crate::app::theme::{Badges, Theme};
+use crate::youtube::auth::app_dir;
+use anyhow::{Context, bail};
+use log::debug;
+use ratatui::style::Color;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub const CONFIG_FILE: &str = "config.toml";
+
+/// Settings for the live chat requests, see `LiveChatMessageListRequest`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChatConfig {
+    pub max_results: u32,
+    pub hl: String,
+}
+
+impl Default for ChatConfig {
+    fn default() -> Self {
+        Self {
+            max_results: 20,
+            hl: "en".to_string(),
+        }
+    }
+}
+
+/// `[avatars]`, their size and how long fetched ones are kept on disk and how much space
+/// they may take.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AvatarConfig {
+    /// Avatars are scaled to this many pixels square when fetched.
+    pub pixels: u32,
+    /// Width of the avatar in front of chat messages, in cells.
+    pub cols: u16,
+    pub cache_ttl_days: u64,
+    pub cache_max_mb: u64,
+}
+
+impl Default for AvatarConfig {
+    fn default() -> Self {
+        Self {
+            pixels: 32,
+            cols: 2,
+            cache_ttl_days: 7,
+            cache_max_mb: 50,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct StatsConfig {
+    interval_secs: u64,
+}
+
+impl Default for StatsConfig {
+    fn default() -> Self {
+        Self { interval_secs: 10 }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(try_from = "String")]
+struct ColorValue(Color);
+
+impl TryFrom<String> for ColorValue {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Color::from_str(&value)
+            .map(ColorValue)
+            .map_err(|_| format!("invalid color \"{}\", use a name or #rrggbb", value))
+    }
+}
+
+/// A `[themes.<name>]` table, colors it leaves out come from `base`.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ThemeFile {
+    base: Option<String>,
+    bg: Option<ColorValue>,
+    border: Option<ColorValue>,
+    text: Option<ColorValue>,
+    text_muted: Option<ColorValue>,
+    sub_bg: Option<ColorValue>,
+    poll_active: Option<ColorValue>,
+    error: Option<ColorValue>,
+    error_quota: Option<ColorValue>,
+    error_auth: Option<ColorValue>,
+    error_not_found: Option<ColorValue>,
+    error_network: Option<ColorValue>,
+    selected: Option<ColorValue>,
+    highlight: Option<ColorValue>,
+    current_match: Option<ColorValue>,
+    owner: Option<ColorValue>,
+    moderator: Option<ColorValue>,
+    member: Option<ColorValue>,
+    help: Option<ColorValue>,
+    nick_palette: Option<Vec<ColorValue>>,
+    stream_palette: Option<Vec<ColorValue>>,
+    tier_palette: Option<Vec<ColorValue>>,
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    theme: String,
+    themes: HashMap<String, ThemeFile>,
+    badges: Badges,
+    max_messages: usize,
+    chat: ChatConfig,
+    stats: StatsConfig,
+    avatars: AvatarConfig,
+    keys: HashMap<KeyAction, Vec<String>>,
+}
+
+impl Default for ConfigFile {
+    fn default() -> Self {
+        Self {
+            theme: "default".to_string(),
+            themes: HashMap::new(),
+            badges: Badges::default(),
+            max_messages: 500,
+            chat: ChatConfig::default(),
+            stats: StatsConfig::default(),
+            avatars: AvatarConfig::default(),
+            keys: HashMap::new(),
+        }
+    }
+}
+
+/// Everything `config.toml` can change, with the built-in defaults for whatever it leaves out.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub theme: Arc<Theme>,
+    pub badges: Arc<Badges>,
+    pub keymap: Arc<KeyMap>,
+    /// Messages kept per view while following the chat.
+    pub max_messages: usize,
+    pub chat: ChatConfig,
+    pub stats_interval: Duration,
+    pub avatars: AvatarConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::resolve(ConfigFile::default()).expect("default config is valid")
+    }
+}
+
+impl Config {
+    /// Reads `config.toml` from the app directory, the defaults when there is none.
+    pub fn load() -> anyhow::Result<Self> {
+        let path = app_dir()?.join(CONFIG_FILE);
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                debug!("no config at {}, using defaults", path.display());
+                return Ok(Self::default());
+            }
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read {}", path.display()));
+            }
+        };
+
+        let file: ConfigFile =
+            toml::from_str(&text).with_context(|| format!("Invalid config {}", path.display()))?;
+        Self::resolve(file).with_context(|| format!("Invalid config {}", path.display()))
+    }
+
+    fn resolve(mut file: ConfigFile) -> anyhow::Result<Self> {
+        if file.max_messages == 0 {
+            bail!("max_messages must be at least 1");
+        }
+        if file.stats.interval_secs == 0 {
+            bail!("stats.interval_secs must be at least 1");
+        }
+        if !(8..=256).contains(&file.avatars.pixels) {
+            bail!("avatars.pixels must be between 8 and 256");
+        }
+        if !(1..=8).contains(&file.avatars.cols) {
+            bail!("avatars.cols must be between 1 and 8");
+        }
+        if file.avatars.cache_ttl_days == 0 {
+            bail!("avatars.cache_ttl_days must be at least 1");
+        }
+        if file.avatars.cache_max_mb == 0 {
+            bail!("avatars.cache_max_mb must be at least 1");
+        }
+        if !(1..=2000).contains(&file.chat.max_results) {
+            bail!("chat.max_results must be between 1 and 2000");
+        }
+
+        let theme = resolve_theme(&file.theme, &mut file.themes)?;
+        Ok(Self {
+            theme: Arc::new(theme),
+            badges: Arc::new(file.badges),
+            keymap: Arc::new(KeyMap::with_overrides(&file.keys)?),
+            max_messages: file.max_messages,
+            chat: file.chat,
+            stats_interval: Duration::from_secs(file.stats.interval_secs),
+            avatars: file.avatars,
+        })
+    }
+}
+
+fn resolve_theme(name: &str, themes: &mut HashMap<String, ThemeFile>) -> anyhow::Result<Theme> {
+    let Some(custom) = themes.remove(name) else {
+        return Theme::builtin(name).with_context(|| {
+            format!(
+                "unknown theme \"{}\", built-in themes are {}",
+                name,
+                Theme::BUILTIN.join(", ")
+            )
+        });
+    };
+
+    // `name` is already out of `themes`, so a theme named like a built-in can extend it
+    let mut theme = resolve_theme(custom.base.as_deref().unwrap_or("default"), themes)?;
+
+    let colors = [
+        (custom.bg, &mut theme.bg),
+        (custom.border, &mut theme.border),
+        (custom.text, &mut theme.text),
+        (custom.text_muted, &mut theme.text_muted),
+        (custom.sub_bg, &mut theme.sub_bg),
+        (custom.poll_active, &mut theme.poll_active),
+        (custom.error, &mut theme.error),
+        (custom.error_quota, &mut theme.error_quota),
+        (custom.error_auth, &mut theme.error_auth),
+        (custom.error_not_found, &mut theme.error_not_found),
+        (custom.error_network, &mut theme.error_network),
+        (custom.selected, &mut theme.selected),
+        (custom.highlight, &mut theme.highlight),
+        (custom.current_match, &mut theme.current_match),
+        (custom.owner, &mut theme.owner),
+        (custom.moderator, &mut theme.moderator),
+        (custom.member, &mut theme.member),
+        (custom.help, &mut theme.help),
+    ];
+    for (value, slot) in colors {
+        if let Some(ColorValue(color)) = value {
+            *slot = color;
+        }
+    }
+    let palettes = [
+        ("nick_palette", custom.nick_palette, &mut theme.nick_palette),
+        (
+            "stream_palette",
+            custom.stream_palette,
+            &mut theme.stream_palette,
+        ),
+        ("tier_palette", custom.tier_palette, &mut theme.tier_palette),
+    ];
+    for (key, value, slot) in palettes {
+        if let Some(palette) = value {
+            if palette.is_empty() {
+                bail!("theme \"{}\" has an empty {}", name, key);
+            }
+            *slot = palette.into_iter().map(|ColorValue(c)| c).collect();
+        }
+    }
+
+    Ok(theme)
+}
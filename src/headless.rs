@@ -0,0 +1,60 @@
+use crate::app::event::{AppEvent, StatusEvent, StreamEvent, StreamId};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::io::{Write, stdout};
+use tokio::sync::mpsc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// One JSON object per line.
+    Ndjson,
+}
+
+#[derive(Serialize)]
+struct Record<'a> {
+    stream: StreamId,
+    #[serde(flatten)]
+    event: &'a StreamEvent,
+}
+
+/// Writes every stream event to stdout until all streams have ended or their chat is gone.
+pub async fn run_headless(
+    mut rx: mpsc::Receiver<AppEvent>,
+    streams: usize,
+    format: OutputFormat,
+) -> anyhow::Result<()> {
+    let mut ended: HashSet<StreamId> = HashSet::new();
+
+    while let Some(ev) = rx.recv().await {
+        let AppEvent::Stream(stream, event) = ev else {
+            continue;
+        };
+
+        let mut out = stdout().lock();
+        match format {
+            OutputFormat::Ndjson => {
+                serde_json::to_writer(
+                    &mut out,
+                    &Record {
+                        stream,
+                        event: &event,
+                    },
+                )?;
+                writeln!(out)?;
+            }
+        }
+        out.flush()?;
+
+        if let StreamEvent::Status(StatusEvent::Disconnected(reason)) = &event
+            && reason.is_final()
+        {
+            ended.insert(stream);
+            if ended.len() == streams {
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -11,15 +11,15 @@ pub fn spawn_input_task(tx: mpsc::Sender<AppEvent>) -> tokio::task::JoinHandle<(
                 break;
             }
 
-            if event::poll(Duration::from_millis(50)).unwrap_or(false) {
-                if let Ok(Event::Key(key)) = event::read() {
-                    if key.kind != KeyEventKind::Press {
-                        continue;
-                    }
+            if event::poll(Duration::from_millis(50)).unwrap_or(false)
+                && let Ok(Event::Key(key)) = event::read()
+            {
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
 
-                    if tx.blocking_send(AppEvent::Input(key)).is_err() {
-                        break;
-                    }
+                if tx.blocking_send(AppEvent::Input(key)).is_err() {
+                    break;
                 }
             }
         }
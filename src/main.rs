@@ -1,18 +1,46 @@
 mod app;
+mod archive;
+mod avatar_cache;
+mod avatar_fetcher;
+mod config;
+mod headless;
 mod input_task;
-mod youtube;
+mod mock_server;
+mod replay;
 mod stats_task;
+mod tick_task;
+mod youtube;
 
-use clap::ArgGroup;
-use clap::Parser;
-use crate::app::App;
+use crate::app::event::{AppEvent, StreamSender};
+use crate::app::graphics::GraphicsMode;
+use crate::app::time::{TimeFormat, TimeStyle, Zone};
+use crate::app::{App, StreamLayout, StreamTarget};
+use crate::archive::{ARCHIVE_FILE, Archive, spawn_archive_task};
+use crate::avatar_cache::{AVATAR_DIR, AvatarCache};
+use crate::avatar_fetcher::AvatarFetcher;
+use crate::config::Config;
+use crate::headless::{OutputFormat, run_headless};
 use crate::input_task::spawn_input_task;
-use crate::youtube::api::YoutubeService;
-use crate::youtube::auth::auth;
-use crate::youtube::spawn_youtube_chat_task;
-use log::debug;
-use tokio::sync::mpsc;
+use crate::mock_server::run_mock_server;
+use crate::replay::{
+    RecordedStream, RecordingHeader, read_recording, spawn_record_task, spawn_replay_task,
+};
 use crate::stats_task::spawn_stats_task;
+use crate::tick_task::spawn_tick_task;
+use crate::youtube::api::{
+    DEFAULT_API_URL, DEFAULT_GRPC_URL, Endpoints, LiveVideoDetails, YoutubeService,
+};
+use crate::youtube::auth::{NoTokens, TokenProvider, app_dir, auth};
+use crate::youtube::{spawn_command_task, spawn_youtube_chat_task};
+use anyhow::bail;
+use chrono_tz::Tz;
+use clap::ArgGroup;
+use clap::{Parser, Subcommand};
+use log::{debug, warn};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::mpsc;
 
 pub mod youtube_api_v3 {
     tonic::include_proto!("youtube.api.v3");
@@ -20,20 +48,272 @@ pub mod youtube_api_v3 {
 #[derive(Parser, Debug)]
 #[command(
     name = "ytc",
+    subcommand_negates_reqs = true,
     group(
         ArgGroup::new("input")
             .required(true)
-            .args(["video", "channel"])
+            .multiple(true)
+            .args(["video", "channel", "live_chat_id"])
     )
 )]
 struct Args {
-    /// Video ID
+    #[command(subcommand)]
+    command: Option<Commands>,
+
+    /// Video ID, repeat to watch several streams
     #[arg(short = 'v', long = "video-id")]
-    video: Option<String>,
+    video: Vec<String>,
 
-    /// Channel Name
+    /// Channel Name, repeat to watch several streams
     #[arg(short = 'c', long = "channel-name")]
-    channel: Option<String>,
+    channel: Vec<String>,
+
+    /// Live chat ID, connects straight to the chat without looking up a video
+    #[arg(long = "live-chat-id")]
+    live_chat_id: Vec<String>,
+
+    /// Base URL of the YouTube Data API
+    #[arg(long = "api-url", default_value = DEFAULT_API_URL)]
+    api_url: String,
+
+    /// Base URL of the live chat gRPC service, plaintext http:// endpoints get no OAuth token
+    #[arg(long = "grpc-url", default_value = DEFAULT_GRPC_URL)]
+    grpc_url: String,
+
+    /// Request write access so messages can be sent from the TUI
+    #[arg(short = 'w', long = "write", conflicts_with = "headless")]
+    write: bool,
+
+    /// How to show several streams at once
+    #[arg(short = 'l', long = "layout", value_enum, default_value_t = StreamLayout::Tiled, global = true)]
+    layout: StreamLayout,
+
+    /// Store every received message in a local SQLite archive
+    #[arg(short = 'a', long = "archive")]
+    archive: bool,
+
+    /// Record the raw chat pages to a file for `ytc replay`
+    #[arg(short = 'r', long = "record")]
+    record: Option<PathBuf>,
+
+    /// Print chat events to stdout instead of starting the TUI
+    #[arg(long = "headless", global = true)]
+    headless: bool,
+
+    /// Output format in headless mode
+    #[arg(long = "format", value_enum, default_value_t = OutputFormat::Ndjson, requires = "headless", global = true)]
+    format: OutputFormat,
+
+    /// IANA time zone for message times, e.g. Europe/Berlin, defaults to the local zone
+    #[arg(long = "timezone", global = true)]
+    timezone: Option<Tz>,
+
+    /// Show times on a 12-hour clock
+    #[arg(long = "12h", global = true)]
+    hour12: bool,
+
+    /// How message times are shown, [T] cycles through them in the TUI
+    #[arg(long = "time-style", value_enum, default_value_t = TimeStyle::Clock, global = true)]
+    time_style: TimeStyle,
+
+    /// How avatars are drawn, auto asks the terminal what it supports
+    #[arg(long = "graphics", value_enum, default_value_t = GraphicsMode::Auto, global = true)]
+    graphics: GraphicsMode,
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Play back a chat session recorded with --record, no network needed
+    Replay {
+        file: PathBuf,
+
+        /// Playback speed, 2 plays twice as fast as recorded
+        #[arg(short = 's', long = "speed", default_value_t = 1.0, value_parser = parse_speed)]
+        speed: f64,
+    },
+    /// Serve a recording as a local live chat gRPC service, see --grpc-url
+    MockServer {
+        file: PathBuf,
+
+        #[arg(long = "listen", default_value = "127.0.0.1:50051")]
+        listen: SocketAddr,
+
+        /// Playback speed, 2 plays twice as fast as recorded
+        #[arg(short = 's', long = "speed", default_value_t = 1.0, value_parser = parse_speed)]
+        speed: f64,
+    },
+}
+
+/// Replay speeds below this would push the recorded timing past what `Duration` holds.
+const MIN_SPEED: f64 = 0.01;
+
+/// `--speed`, a finite factor of at least `MIN_SPEED`.
+fn parse_speed(value: &str) -> Result<f64, String> {
+    let speed: f64 = value.parse().map_err(|e| format!("{}", e))?;
+    if !speed.is_finite() || speed < MIN_SPEED {
+        return Err(format!("must be a number of at least {}", MIN_SPEED));
+    }
+    Ok(speed)
+}
+
+/// The avatar fetcher, `None` when the cache can't be opened so chat works without avatars.
+fn avatar_fetcher(config: &Config, tx: &mpsc::Sender<AppEvent>) -> Option<AvatarFetcher> {
+    let fetcher = app_dir()
+        .and_then(|dir| AvatarCache::open(&dir.join(AVATAR_DIR), &config.avatars))
+        .and_then(|cache| AvatarFetcher::new(cache, tx.clone()));
+    match fetcher {
+        Ok(fetcher) => Some(fetcher),
+        Err(e) => {
+            warn!("avatars are disabled: {:#}", e);
+            None
+        }
+    }
+}
+
+/// Resolves the requested streams and starts their tasks.
+async fn start_live(
+    args: &Args,
+    config: &Config,
+    tx: &mpsc::Sender<AppEvent>,
+) -> anyhow::Result<(Vec<StreamTarget>, Option<PathBuf>)> {
+    // a plaintext chat endpoint with known chat ids is the offline setup, no sign-in needed
+    let needs_auth = args.write
+        || !args.video.is_empty()
+        || !args.channel.is_empty()
+        || !args.grpc_url.starts_with("http://");
+    let tokens: Arc<dyn TokenProvider> = if needs_auth {
+        Arc::new(auth(args.write).await?)
+    } else {
+        Arc::new(NoTokens)
+    };
+    let endpoints = Endpoints {
+        api: args.api_url.clone(),
+        grpc: args.grpc_url.clone(),
+    };
+    // avatars are only drawn by the TUI, and an offline setup has no CDN to fetch them from
+    let avatars = if needs_auth && !args.headless && args.graphics != GraphicsMode::None {
+        avatar_fetcher(config, tx)
+    } else {
+        None
+    };
+    let yt_service = YoutubeService::new(tokens, endpoints, config.chat.clone(), avatars)?;
+    let mut video_ids = args.video.clone();
+    for channel_name in &args.channel {
+        video_ids.push(
+            yt_service
+                .find_video_id_by_channel_name(channel_name)
+                .await?,
+        );
+    }
+    let mut live_videos = Vec::with_capacity(video_ids.len());
+    for video_id in video_ids {
+        let live_video = yt_service
+            .find_live_video_details_by_video_id(&video_id)
+            .await?;
+        live_videos.push((Some(video_id), live_video));
+    }
+    for chat_id in &args.live_chat_id {
+        live_videos.push((
+            None,
+            LiveVideoDetails {
+                chat_id: chat_id.clone(),
+                channel_name: chat_id.clone(),
+            },
+        ));
+    }
+
+    let archive_path = app_dir()?.join(ARCHIVE_FILE);
+    let archive_writer = if args.archive {
+        Some(spawn_archive_task(&archive_path)?)
+    } else {
+        None
+    };
+    let recorder = match &args.record {
+        Some(path) => {
+            let header = RecordingHeader {
+                streams: live_videos
+                    .iter()
+                    .map(|(video_id, live_video)| RecordedStream {
+                        title: live_video.channel_name.clone(),
+                        video_id: video_id
+                            .clone()
+                            .unwrap_or_else(|| live_video.chat_id.clone()),
+                    })
+                    .collect(),
+            };
+            Some(spawn_record_task(path, header)?)
+        }
+        None => None,
+    };
+
+    let mut targets = Vec::with_capacity(live_videos.len());
+    for (stream, (video_id, live_video)) in live_videos.into_iter().enumerate() {
+        let stream_tx = StreamSender::new(stream, tx.clone());
+        // viewer counts need a video, chats joined by id go without
+        if let Some(video_id) = &video_id {
+            spawn_stats_task(
+                video_id.clone(),
+                yt_service.clone(),
+                stream_tx.clone(),
+                config.stats_interval,
+            );
+        }
+        let video_id = video_id.unwrap_or_else(|| live_video.chat_id.clone());
+        let commands = if args.write {
+            let (command_tx, command_rx) = mpsc::channel(16);
+            spawn_command_task(
+                yt_service.clone(),
+                live_video.chat_id.clone(),
+                command_rx,
+                stream_tx.clone(),
+            );
+            Some(command_tx)
+        } else {
+            None
+        };
+        spawn_youtube_chat_task(
+            yt_service.clone(),
+            live_video.chat_id,
+            stream_tx,
+            archive_writer.as_ref().map(|w| w.sink(&video_id)),
+            recorder.as_ref().map(|r| r.sink(stream)),
+        );
+        targets.push(StreamTarget {
+            title: live_video.channel_name,
+            video_id,
+            commands,
+        });
+    }
+
+    Ok((targets, archive_writer.map(|_| archive_path)))
+}
+
+fn start_replay(
+    file: &Path,
+    speed: f64,
+    tx: &mpsc::Sender<AppEvent>,
+) -> anyhow::Result<Vec<StreamTarget>> {
+    let (header, pages) = read_recording(file)?;
+    debug!(
+        "replaying {} pages of {} streams",
+        pages.len(),
+        header.streams.len()
+    );
+
+    let senders = (0..header.streams.len())
+        .map(|stream| StreamSender::new(stream, tx.clone()))
+        .collect();
+    spawn_replay_task(pages, speed, senders);
+
+    Ok(header
+        .streams
+        .into_iter()
+        .map(|s| StreamTarget {
+            title: s.title,
+            video_id: s.video_id,
+            commands: None,
+        })
+        .collect())
 }
 
 #[tokio::main]
@@ -41,28 +321,52 @@ async fn main() -> anyhow::Result<()> {
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
     debug!("application start");
 
-    let token = auth().await?;
-    let yt_service = YoutubeService::new(&token)?;
     let args = Args::parse();
-    let video_id = match (args.video, args.channel) {
-        (Some(video_id), None) => video_id,
-        (None, Some(channel_name)) => {
-            yt_service.find_video_id_by_channel_name(&channel_name).await?
-        }
-        _ => {
-            unreachable!("Please pass either --video-id or --chanel-name")
+    let config = Config::load()?;
+    let (tx, rx) = mpsc::channel(100);
+    let (targets, archive_path) = match &args.command {
+        Some(Commands::Replay { file, speed }) => (start_replay(file, *speed, &tx)?, None),
+        Some(Commands::MockServer {
+            file,
+            listen,
+            speed,
+        }) => {
+            return run_mock_server(file, *listen, *speed).await;
         }
+        None => start_live(&args, &config, &tx).await?,
     };
-    let live_video = yt_service.find_live_video_details_by_video_id(&video_id).await?;
+    if targets.is_empty() {
+        bail!("nothing to show, the recording has no streams");
+    }
 
-    let mut terminal = ratatui::init();
-    let (tx, rx) = mpsc::channel(100);
+    if args.headless {
+        return run_headless(rx, targets.len(), args.format).await;
+    }
 
-    spawn_input_task(tx.clone());
-    spawn_stats_task(video_id, yt_service.clone(), tx.clone());
-    spawn_youtube_chat_task(yt_service, live_video.chat_id, tx);
+    let archive = match archive_path {
+        Some(path) => Some(Archive::open(&path)?),
+        None => None,
+    };
+    let time_format = TimeFormat {
+        zone: args.timezone.map_or(Zone::Local, Zone::Named),
+        hour12: args.hour12,
+        style: args.time_style,
+    };
 
-    let app = App::new(live_video.channel_name);
+    let mut terminal = ratatui::init();
+    // the terminal answers the graphics queries on stdin, so ask before the input task reads it
+    let graphics = args.graphics.resolve();
+    spawn_input_task(tx.clone());
+    spawn_tick_task(tx.clone());
+    let app = App::new(
+        targets,
+        args.layout,
+        archive,
+        time_format,
+        &config,
+        graphics,
+        tx.clone(),
+    );
 
     app.run(&mut terminal, rx).await?;
     ratatui::restore();
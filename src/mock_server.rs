@@ -0,0 +1,204 @@
+use crate::replay::{RecordedPage, RecordingHeader, read_recording};
+use crate::youtube_api_v3::v3_data_live_chat_message_service_server::{
+    V3DataLiveChatMessageService, V3DataLiveChatMessageServiceServer,
+};
+use crate::youtube_api_v3::{LiveChatMessageListRequest, LiveChatMessageListResponse};
+use log::{debug, info};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status};
+
+type ScriptedPage = (u64, LiveChatMessageListResponse);
+
+/// Serves the pages of a recording as `V3DataLiveChatMessageService`. Each
+/// recorded stream is a live chat whose id is its video id, page tokens are
+/// indexes into its pages.
+struct MockChatService {
+    chats: Vec<(String, Arc<Vec<ScriptedPage>>)>,
+    speed: f64,
+}
+
+#[tonic::async_trait]
+impl V3DataLiveChatMessageService for MockChatService {
+    type StreamListStream = ReceiverStream<Result<LiveChatMessageListResponse, Status>>;
+
+    async fn stream_list(
+        &self,
+        request: Request<LiveChatMessageListRequest>,
+    ) -> Result<Response<Self::StreamListStream>, Status> {
+        let request = request.into_inner();
+        let chat_id = request.live_chat_id.unwrap_or_default();
+        let Some((_, pages)) = self.chats.iter().find(|(id, _)| *id == chat_id) else {
+            return Err(Status::not_found(format!(
+                "live chat {} not found",
+                chat_id
+            )));
+        };
+        let start = match request.page_token.as_deref() {
+            Some(token) => token
+                .parse::<usize>()
+                .map_err(|_| Status::invalid_argument("invalid page token"))?,
+            None => 0,
+        };
+        debug!("mock stream_list chat={} from page={}", chat_id, start);
+
+        let pages = pages.clone();
+        let speed = self.speed;
+        let (tx, rx) = mpsc::channel(4);
+        tokio::spawn(async move {
+            let started = Instant::now();
+            let offset = pages.get(start).map(|(elapsed, _)| *elapsed).unwrap_or(0);
+            for (i, (elapsed, page)) in pages.iter().enumerate().skip(start) {
+                let due = started + Duration::from_millis(elapsed - offset).div_f64(speed);
+                tokio::time::sleep_until(due.into()).await;
+
+                let mut page = page.clone();
+                // the last page has no token, which ends the chat for the client
+                page.next_page_token = (i + 1 < pages.len()).then(|| (i + 1).to_string());
+                if tx.send(Ok(page)).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+}
+
+impl MockChatService {
+    fn new(header: &RecordingHeader, pages: &[RecordedPage], speed: f64) -> Self {
+        let chats = header
+            .streams
+            .iter()
+            .enumerate()
+            .map(|(stream, recorded)| {
+                let scripted: Vec<ScriptedPage> = pages
+                    .iter()
+                    .filter(|p| p.stream as usize == stream)
+                    .filter_map(|p| Some((p.elapsed_ms, p.response.clone()?)))
+                    .collect();
+                info!(
+                    "serving {} ({} pages) as live chat id {}",
+                    recorded.title,
+                    scripted.len(),
+                    recorded.video_id
+                );
+                (recorded.video_id.clone(), Arc::new(scripted))
+            })
+            .collect();
+
+        Self { chats, speed }
+    }
+}
+
+/// Serves a recording over plaintext gRPC on `addr` until interrupted.
+pub async fn run_mock_server(file: &Path, addr: SocketAddr, speed: f64) -> anyhow::Result<()> {
+    let (header, pages) = read_recording(file)?;
+    let service = MockChatService::new(&header, &pages, speed);
+
+    info!("mock chat server listening on {}", addr);
+    tonic::transport::Server::builder()
+        .add_service(V3DataLiveChatMessageServiceServer::new(service))
+        .serve(addr)
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay::RecordedStream;
+    use crate::youtube::auth::NoTokens;
+    use crate::youtube::source::{ChatSource, GrpcChatSource};
+    use crate::youtube_api_v3::LiveChatMessage;
+    use tonic::transport::server::TcpIncoming;
+
+    fn recorded(stream: u32, elapsed_ms: u64, id: &str) -> RecordedPage {
+        RecordedPage {
+            elapsed_ms,
+            stream,
+            response: Some(LiveChatMessageListResponse {
+                items: vec![LiveChatMessage {
+                    id: Some(id.to_string()),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }),
+        }
+    }
+
+    fn request(chat_id: &str, page_token: Option<&str>) -> LiveChatMessageListRequest {
+        LiveChatMessageListRequest {
+            live_chat_id: Some(chat_id.to_string()),
+            page_token: page_token.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn serves_recording_to_grpc_client() {
+        let header = RecordingHeader {
+            streams: vec![
+                RecordedStream {
+                    title: "first".to_string(),
+                    video_id: "chat-a".to_string(),
+                },
+                RecordedStream {
+                    title: "second".to_string(),
+                    video_id: "chat-b".to_string(),
+                },
+            ],
+        };
+        let pages = vec![
+            recorded(0, 0, "a1"),
+            recorded(1, 5, "b1"),
+            recorded(0, 10, "a2"),
+            recorded(0, 20, "a3"),
+        ];
+        let service = MockChatService::new(&header, &pages, 100.0);
+
+        let incoming = TcpIncoming::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = incoming.local_addr().unwrap();
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(V3DataLiveChatMessageServiceServer::new(service))
+                .serve_with_incoming(incoming),
+        );
+        let source = GrpcChatSource::new(&format!("http://{}", addr), Arc::new(NoTokens)).unwrap();
+
+        let mut stream = source.stream_list(request("chat-a", None)).await.unwrap();
+        let mut received = Vec::new();
+        while let Some(page) = stream.message().await.unwrap() {
+            received.push((page.items[0].id.clone().unwrap(), page.next_page_token));
+        }
+        assert_eq!(
+            received,
+            vec![
+                ("a1".to_string(), Some("1".to_string())),
+                ("a2".to_string(), Some("2".to_string())),
+                ("a3".to_string(), None),
+            ]
+        );
+
+        // a page token resumes where an earlier connection stopped
+        let mut stream = source
+            .stream_list(request("chat-a", Some("2")))
+            .await
+            .unwrap();
+        let page = stream.message().await.unwrap().unwrap();
+        assert_eq!(page.items[0].id.as_deref(), Some("a3"));
+        assert!(stream.message().await.unwrap().is_none());
+
+        let missing = source
+            .stream_list(request("chat-c", None))
+            .await
+            .unwrap_err();
+        let status = missing.downcast_ref::<Status>().unwrap();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+    }
+}
@@ -0,0 +1,242 @@
+use crate::app::event::{DisconnectReason, StatusEvent, StreamEvent, StreamSender};
+use crate::youtube::api::{PageState, StreamCursor, process_page};
+use crate::youtube_api_v3::LiveChatMessageListResponse;
+use anyhow::Context;
+use log::debug;
+use prost::Message;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+/// First entry of a recording, describes the streams the pages belong to.
+#[derive(Clone, PartialEq, Message)]
+pub struct RecordingHeader {
+    #[prost(message, repeated, tag = "1")]
+    pub streams: Vec<RecordedStream>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct RecordedStream {
+    #[prost(string, tag = "1")]
+    pub title: String,
+    #[prost(string, tag = "2")]
+    pub video_id: String,
+}
+
+/// A page as received from `stream_list`, `elapsed_ms` counts from the start of the recording.
+#[derive(Clone, PartialEq, Message)]
+pub struct RecordedPage {
+    #[prost(uint64, tag = "1")]
+    pub elapsed_ms: u64,
+    #[prost(uint32, tag = "2")]
+    pub stream: u32,
+    #[prost(message, optional, tag = "3")]
+    pub response: Option<LiveChatMessageListResponse>,
+}
+
+/// Handle given to a stream's chat task to record the pages it receives.
+#[derive(Clone)]
+pub struct RecordSink {
+    stream: u32,
+    started: Instant,
+    tx: mpsc::UnboundedSender<RecordedPage>,
+}
+
+impl RecordSink {
+    pub fn record(&self, response: &LiveChatMessageListResponse) {
+        let _ = self.tx.send(RecordedPage {
+            elapsed_ms: self.started.elapsed().as_millis() as u64,
+            stream: self.stream,
+            response: Some(response.clone()),
+        });
+    }
+}
+
+/// Sending end of the recording writer thread.
+pub struct Recorder {
+    started: Instant,
+    tx: mpsc::UnboundedSender<RecordedPage>,
+}
+
+impl Recorder {
+    pub fn sink(&self, stream: usize) -> RecordSink {
+        RecordSink {
+            stream: stream as u32,
+            started: self.started,
+            tx: self.tx.clone(),
+        }
+    }
+}
+
+/// Creates the recording file and starts writing pages to it as length-delimited protobuf.
+pub fn spawn_record_task(path: &Path, header: RecordingHeader) -> anyhow::Result<Recorder> {
+    let file = File::create(path)
+        .with_context(|| format!("Failed to create recording {}", path.display()))?;
+    let mut out = BufWriter::new(file);
+    out.write_all(&header.encode_length_delimited_to_vec())?;
+    out.flush()?;
+
+    let (tx, mut rx) = mpsc::unbounded_channel::<RecordedPage>();
+    std::thread::spawn(move || {
+        while let Some(page) = rx.blocking_recv() {
+            // flush every page so an interrupted session still leaves a usable file
+            let written = out
+                .write_all(&page.encode_length_delimited_to_vec())
+                .and_then(|_| out.flush());
+            if let Err(e) = written {
+                debug!("recording page failed: {:#}", e);
+                break;
+            }
+        }
+    });
+
+    Ok(Recorder {
+        started: Instant::now(),
+        tx,
+    })
+}
+
+pub fn read_recording(path: &Path) -> anyhow::Result<(RecordingHeader, Vec<RecordedPage>)> {
+    let bytes = std::fs::read(path)
+        .with_context(|| format!("Failed to read recording {}", path.display()))?;
+    let mut buf = bytes.as_slice();
+    let header =
+        RecordingHeader::decode_length_delimited(&mut buf).context("Not a chat recording")?;
+
+    let mut pages = Vec::new();
+    while !buf.is_empty() {
+        match RecordedPage::decode_length_delimited(&mut buf) {
+            Ok(page) => pages.push(page),
+            Err(e) => {
+                // the recording was cut off mid-page, replay what's complete
+                debug!("stopping at truncated page: {}", e);
+                break;
+            }
+        }
+    }
+
+    Ok((header, pages))
+}
+
+/// Feeds recorded pages through the same conversion as the live stream, at the
+/// recorded timing divided by `speed`.
+pub fn spawn_replay_task(
+    pages: Vec<RecordedPage>,
+    speed: f64,
+    senders: Vec<StreamSender>,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut streams: Vec<(StreamCursor, PageState)> =
+            senders.iter().map(|_| Default::default()).collect();
+        for tx in &senders {
+            let _ = tx.send(StreamEvent::Status(StatusEvent::Connected)).await;
+        }
+
+        let started = Instant::now();
+        for page in pages {
+            let stream = page.stream as usize;
+            let (Some(tx), Some(response)) = (senders.get(stream), page.response) else {
+                continue;
+            };
+            let due = started + Duration::from_millis(page.elapsed_ms).div_f64(speed);
+            tokio::time::sleep_until(due.into()).await;
+
+            let (cursor, state) = &mut streams[stream];
+            if let Err(e) = process_page(None, &response, cursor, state, tx, None).await {
+                debug!("replay stopped: {:#}", e);
+                return;
+            }
+        }
+
+        for (tx, (_, state)) in senders.iter().zip(streams) {
+            let _ = tx
+                .send(StreamEvent::Status(StatusEvent::Disconnected(
+                    DisconnectReason::StreamEnded { at: state.ended_at },
+                )))
+                .await;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::youtube_api_v3::LiveChatMessage;
+
+    fn page(id: &str) -> LiveChatMessageListResponse {
+        LiveChatMessageListResponse {
+            items: vec![LiveChatMessage {
+                id: Some(id.to_string()),
+                ..Default::default()
+            }],
+            next_page_token: Some(format!("after-{}", id)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn recording_round_trips() {
+        let path = std::env::temp_dir().join(format!("ytc-recording-{}.bin", std::process::id()));
+        let header = RecordingHeader {
+            streams: vec![
+                RecordedStream {
+                    title: "first".to_string(),
+                    video_id: "video-a".to_string(),
+                },
+                RecordedStream {
+                    title: "second".to_string(),
+                    video_id: "video-b".to_string(),
+                },
+            ],
+        };
+
+        let recorder = spawn_record_task(&path, header.clone()).unwrap();
+        recorder.sink(0).record(&page("a1"));
+        recorder.sink(1).record(&page("b1"));
+        recorder.sink(0).record(&page("a2"));
+        drop(recorder);
+
+        // the writer thread flushes every page, wait until it got to the last one
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let (read_header, pages) = loop {
+            let (read_header, pages) = read_recording(&path).unwrap();
+            if pages.len() == 3 || Instant::now() > deadline {
+                break (read_header, pages);
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        };
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(read_header, header);
+        let recorded: Vec<(u32, LiveChatMessageListResponse)> = pages
+            .into_iter()
+            .map(|p| (p.stream, p.response.unwrap()))
+            .collect();
+        assert_eq!(
+            recorded,
+            vec![(0, page("a1")), (1, page("b1")), (0, page("a2"))]
+        );
+    }
+
+    #[test]
+    fn truncated_recording_keeps_complete_pages() {
+        let path = std::env::temp_dir().join(format!("ytc-truncated-{}.bin", std::process::id()));
+        let header = RecordingHeader { streams: vec![] };
+        let first = RecordedPage {
+            elapsed_ms: 5,
+            stream: 0,
+            response: Some(page("a1")),
+        };
+        let mut bytes = header.encode_length_delimited_to_vec();
+        bytes.extend(first.encode_length_delimited_to_vec());
+        let second = first.encode_length_delimited_to_vec();
+        bytes.extend(&second[..second.len() / 2]);
+        std::fs::write(&path, bytes).unwrap();
+
+        let (_, pages) = read_recording(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(pages, vec![first]);
+    }
+}
@@ -1,17 +1,18 @@
-use crate::app::event::{AppEvent, StatsMessage};
+use crate::app::event::{StatsMessage, StreamEvent, StreamSender};
 use crate::youtube::api::YoutubeService;
-use std::time::Duration;
+use crate::youtube::error::app_error;
 use log::debug;
-use tokio::sync::mpsc;
+use std::time::Duration;
 use tokio::time::{MissedTickBehavior, interval};
 
 pub fn spawn_stats_task(
     live_video_id: String,
     yt: YoutubeService,
-    tx: mpsc::Sender<AppEvent>,
+    tx: StreamSender,
+    period: Duration,
 ) -> tokio::task::JoinHandle<()> {
     tokio::task::spawn(async move {
-        let mut interval = interval(Duration::from_secs(10));
+        let mut interval = interval(period);
         interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
 
         loop {
@@ -21,7 +22,7 @@ pub fn spawn_stats_task(
                 Ok(viewer_count) => {
                     debug!("fetched viewer count as {}", viewer_count);
                     if tx
-                        .send(AppEvent::StatsUpdate(StatsMessage {
+                        .send(StreamEvent::StatsUpdate(StatsMessage {
                             viewer_count: viewer_count.parse::<u32>().unwrap_or(0),
                         }))
                         .await
@@ -30,8 +31,15 @@ pub fn spawn_stats_task(
                         break;
                     }
                 }
-                _ => {
-                    //todo: send err message
+                Err(e) => {
+                    debug!("viewer count fetch failed: {:#}", e);
+                    if tx
+                        .send(StreamEvent::Error(app_error("viewer count", &e)))
+                        .await
+                        .is_err()
+                    {
+                        break;
+                    }
                 }
             }
         }
@@ -0,0 +1,20 @@
+use crate::app::event::AppEvent;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::{MissedTickBehavior, interval};
+
+/// Periodic redraws for time-based UI such as expiring toasts.
+pub fn spawn_tick_task(tx: mpsc::Sender<AppEvent>) -> tokio::task::JoinHandle<()> {
+    tokio::task::spawn(async move {
+        let mut interval = interval(Duration::from_secs(1));
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+        loop {
+            interval.tick().await;
+
+            if tx.send(AppEvent::Tick).await.is_err() {
+                break;
+            }
+        }
+    })
+}
@@ -1,27 +1,74 @@
-use crate::app::event::{AppEvent, ChatMessage, KittyAvatar, MessageKind};
-use crate::youtube::models::{SearchResponse, VideoListResponse};
-use crate::youtube_api_v3::LiveChatMessageListRequest;
-use crate::youtube_api_v3::v3_data_live_chat_message_service_client::V3DataLiveChatMessageServiceClient;
+use crate::app::event::{
+    ChatMessage, DisconnectReason, MessageKind, ModerationEvent, Poll, PollOption, PollStatus,
+    StatusEvent, StreamEvent, StreamId, StreamSender,
+};
+use crate::archive::ArchiveSink;
+use crate::avatar_fetcher::AvatarFetcher;
+use crate::config::ChatConfig;
+use crate::replay::RecordSink;
+use crate::youtube::auth::TokenProvider;
+use crate::youtube::error::ApiError;
+use crate::youtube::models::{LiveChatMessageResource, SearchResponse, VideoListResponse};
+use crate::youtube::source::{ChatSource, GrpcChatSource};
+use crate::youtube_api_v3::live_chat_message_snippet::DisplayedContent;
+use crate::youtube_api_v3::live_chat_message_snippet::type_wrapper::Type as MessageType;
+use crate::youtube_api_v3::live_chat_poll_details::poll_status_wrapper::PollStatus as ProtoPollStatus;
+use crate::youtube_api_v3::live_chat_user_banned_message_details::ban_type_wrapper::BanType;
+use crate::youtube_api_v3::{
+    LiveChatMessage, LiveChatMessageListRequest, LiveChatMessageListResponse,
+    LiveChatMessageSnippet,
+};
 use anyhow::{Context, bail};
-use image::imageops::FilterType;
+use chrono::{DateTime, Utc};
 use log::debug;
 use reqwest::Url;
-use reqwest::header::{AUTHORIZATION, HeaderValue};
-use serde_json::Value::Bool;
-use std::collections::HashMap;
-use std::collections::hash_map::DefaultHasher;
-use std::fs::File;
-use std::hash::{Hash, Hasher};
-use std::io::Write;
+use reqwest::header::CONTENT_TYPE;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::Arc;
-use tokio::sync::mpsc;
-use tonic::Request;
-use tonic::metadata::MetadataValue;
-use tonic::transport::{Channel, ClientTlsConfig};
+
+const SEEN_IDS_CAPACITY: usize = 2000;
+
+/// Resume point for `stream_chat`, kept by the caller across reconnects.
+#[derive(Default)]
+pub struct StreamCursor {
+    pub next_page_token: Option<String>,
+    seen_ids: HashSet<String>,
+    seen_order: VecDeque<String>,
+}
+
+impl StreamCursor {
+    /// Returns false if the message id was already delivered.
+    fn mark_seen(&mut self, id: &str) -> bool {
+        if !self.seen_ids.insert(id.to_string()) {
+            return false;
+        }
+        self.seen_order.push_back(id.to_string());
+        while self.seen_order.len() > SEEN_IDS_CAPACITY {
+            if let Some(old) = self.seen_order.pop_front() {
+                self.seen_ids.remove(&old);
+            }
+        }
+        true
+    }
+}
+
+pub const DEFAULT_API_URL: &str = "https://www.googleapis.com";
+pub const DEFAULT_GRPC_URL: &str = "https://youtube.googleapis.com";
+
+/// Base URLs of the REST API and the gRPC chat stream.
+pub struct Endpoints {
+    pub api: String,
+    pub grpc: String,
+}
 
 #[derive(Clone)]
 pub struct YoutubeService {
-    token: String,
+    tokens: Arc<dyn TokenProvider>,
+    chat: Arc<dyn ChatSource>,
+    chat_config: ChatConfig,
+    /// `None` when avatars aren't shown.
+    avatars: Option<AvatarFetcher>,
+    api_url: String,
     pub http: reqwest::Client,
 }
 
@@ -31,38 +78,54 @@ pub struct LiveVideoDetails {
 }
 
 impl YoutubeService {
-    pub fn new(token: &str) -> anyhow::Result<YoutubeService> {
-        let mut headers = reqwest::header::HeaderMap::new();
-        headers.insert(
-            AUTHORIZATION,
-            HeaderValue::from_str(&format!("Bearer {}", token))?,
-        );
-
-        let client = reqwest::Client::builder()
-            .default_headers(headers)
-            .build()?;
+    pub fn new(
+        tokens: Arc<dyn TokenProvider>,
+        endpoints: Endpoints,
+        chat_config: ChatConfig,
+        avatars: Option<AvatarFetcher>,
+    ) -> anyhow::Result<YoutubeService> {
+        let client = reqwest::Client::builder().build()?;
+        let chat = Arc::new(GrpcChatSource::new(&endpoints.grpc, tokens.clone())?);
 
         Ok(Self {
-            token: token.to_string(),
+            tokens,
+            chat,
+            chat_config,
+            avatars,
+            api_url: endpoints.api.trim_end_matches('/').to_string(),
             http: client,
         })
     }
 }
 
 impl YoutubeService {
-    fn auth_req(&self, req: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
-        req.bearer_auth(&self.token)
+    async fn auth_req(
+        &self,
+        req: reqwest::RequestBuilder,
+    ) -> anyhow::Result<reqwest::RequestBuilder> {
+        Ok(req.bearer_auth(self.tokens.token().await?))
+    }
+
+    fn api_url(&self, path: &str) -> anyhow::Result<Url> {
+        Ok(Url::parse(&format!(
+            "{}/youtube/v3/{}",
+            self.api_url, path
+        ))?)
     }
 
     async fn make_yt_req(&self, url: Url) -> anyhow::Result<String> {
         debug!("YouTube request: {}", url);
-        let res = self.auth_req(self.http.get(url)).send().await?;
+        self.send_yt_req(self.http.get(url)).await
+    }
+
+    async fn send_yt_req(&self, req: reqwest::RequestBuilder) -> anyhow::Result<String> {
+        let res = self.auth_req(req).await?.send().await?;
         let status = res.status();
         let body = res.text().await?;
         debug!("YouTube response status={} body_len={}", status, body.len());
 
         if !status.is_success() {
-            bail!("YouTube API error ({}): {}", status, body);
+            return Err(ApiError { status, body }.into());
         }
 
         Ok(body)
@@ -70,7 +133,7 @@ impl YoutubeService {
 
     async fn channel_id_by_name(&self, channel_name: &str) -> anyhow::Result<Option<String>> {
         debug!("resolving channel by name query={}", channel_name);
-        let mut url = Url::parse("https://www.googleapis.com/youtube/v3/search")?;
+        let mut url = self.api_url("search")?;
         url.query_pairs_mut()
             .append_pair("part", "snippet")
             .append_pair("q", channel_name)
@@ -83,7 +146,7 @@ impl YoutubeService {
 
         let channel_id = parsed
             .items
-            .get(0)
+            .first()
             .and_then(|i| i.id.as_ref())
             .and_then(|id| id.channel_id.clone());
         debug!("channel lookup result={:?}", channel_id);
@@ -96,7 +159,7 @@ impl YoutubeService {
         channel_id: &str,
     ) -> anyhow::Result<Option<String>> {
         debug!("resolving live video by channel_id={}", channel_id);
-        let mut url = Url::parse("https://www.googleapis.com/youtube/v3/search")?;
+        let mut url = self.api_url("search")?;
         url.query_pairs_mut()
             .append_pair("part", "id")
             .append_pair("channelId", channel_id)
@@ -110,7 +173,7 @@ impl YoutubeService {
 
         let video_id = parsed
             .items
-            .get(0)
+            .first()
             .and_then(|i| i.id.as_ref())
             .and_then(|id| id.video_id.clone());
         debug!("live video lookup result={:?}", video_id);
@@ -123,16 +186,16 @@ impl YoutubeService {
         live_video_id: &str,
     ) -> anyhow::Result<Option<LiveVideoDetails>> {
         debug!("resolving live chat by video_id={}", live_video_id);
-        let mut url = Url::parse("https://www.googleapis.com/youtube/v3/videos")?;
+        let mut url = self.api_url("videos")?;
         url.query_pairs_mut()
             .append_pair("part", "liveStreamingDetails,snippet")
-            .append_pair("id", &live_video_id);
+            .append_pair("id", live_video_id);
 
         let body = self.make_yt_req(url).await?;
         let parsed: VideoListResponse = serde_json::from_str(&body)
             .context("Failed to parse search response (channel lookup)")?;
 
-        let item = parsed.items.get(0);
+        let item = parsed.items.first();
         let chat_id = item
             .and_then(|v| v.live_streaming_details.as_ref())
             .and_then(|d| d.active_live_chat_id.clone());
@@ -174,7 +237,7 @@ impl YoutubeService {
         &self,
         live_stream_id: &str,
     ) -> anyhow::Result<LiveVideoDetails> {
-        let Some(details) = self.find_chat_id_by_live_video_id(&live_stream_id).await? else {
+        let Some(details) = self.find_chat_id_by_live_video_id(live_stream_id).await? else {
             bail!("Couldn't find live chat id");
         };
         debug!(
@@ -190,10 +253,10 @@ impl YoutubeService {
         live_video_id: &str,
     ) -> anyhow::Result<String> {
         debug!("resolving viewer count by video_id={}", live_video_id);
-        let mut url = Url::parse("https://www.googleapis.com/youtube/v3/videos")?;
+        let mut url = self.api_url("videos")?;
         url.query_pairs_mut()
             .append_pair("part", "liveStreamingDetails")
-            .append_pair("id", &live_video_id);
+            .append_pair("id", live_video_id);
 
         let body = self.make_yt_req(url).await?;
         let parsed: VideoListResponse = serde_json::from_str(&body)
@@ -201,7 +264,7 @@ impl YoutubeService {
 
         let viewer_count = parsed
             .items
-            .get(0)
+            .first()
             .and_then(|v| v.live_streaming_details.as_ref())
             .and_then(|d| d.concurrent_viewers.clone());
         debug!("live chat lookup result={:?}", viewer_count);
@@ -211,56 +274,98 @@ impl YoutubeService {
 }
 
 impl YoutubeService {
-    async fn fetch_avatar(&self, avatar_url: &str) -> Option<KittyAvatar> {
-        let response = self.http.get(avatar_url).send().await.ok()?;
-        let bytes = response.bytes().await.ok()?;
-        let image = image::load_from_memory(&bytes).ok()?;
-        let resized = image.resize_to_fill(32, 32, FilterType::Lanczos3);
-        let rgba = resized.to_rgba8();
-        let (width, height) = rgba.dimensions();
-
-        let mut hasher = DefaultHasher::new();
-        avatar_url.hash(&mut hasher);
-        let id = hasher.finish() as u32 & 0x00FF_FFFF;
-
-        let path = std::env::temp_dir().join(format!("ytc-kitty-avatar-{id}.rgba"));
-        let mut file = File::create(&path).ok()?;
-        file.write_all(rgba.as_raw()).ok()?;
-
-        Some(KittyAvatar {
-            id,
-            cols: 2,
-            width,
-            height,
-            path: path.to_string_lossy().into_owned(),
-        })
+    /// Posts a text message to the live chat, returning the id YouTube assigned to it.
+    pub async fn insert_message(&self, live_chat_id: &str, text: &str) -> anyhow::Result<String> {
+        debug!("inserting chat message live_chat_id={}", live_chat_id);
+        let mut url = self.api_url("liveChat/messages")?;
+        url.query_pairs_mut().append_pair("part", "snippet");
+
+        let body = serde_json::json!({
+            "snippet": {
+                "liveChatId": live_chat_id,
+                "type": "textMessageEvent",
+                "textMessageDetails": { "messageText": text },
+            }
+        });
+        let req = self
+            .http
+            .post(url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.to_string());
+        let body = self.send_yt_req(req).await?;
+        let parsed: LiveChatMessageResource = serde_json::from_str(&body)
+            .context("Failed to parse live chat message insert response")?;
+        debug!("inserted chat message id={}", parsed.id);
+
+        Ok(parsed.id)
+    }
+}
+
+impl YoutubeService {
+    pub async fn delete_message(&self, message_id: &str) -> anyhow::Result<()> {
+        debug!("deleting chat message id={}", message_id);
+        let mut url = self.api_url("liveChat/messages")?;
+        url.query_pairs_mut().append_pair("id", message_id);
+
+        self.send_yt_req(self.http.delete(url)).await?;
+        Ok(())
     }
 
-    pub async fn stream_chat(
+    /// Bans a user from the live chat, temporarily when `duration_secs` is set.
+    pub async fn ban_user(
         &self,
         live_chat_id: &str,
-        tx: mpsc::Sender<AppEvent>,
+        channel_id: &str,
+        duration_secs: Option<u64>,
     ) -> anyhow::Result<()> {
-        debug!("listen start live_chat_id={}", live_chat_id);
-        let tls = ClientTlsConfig::new().with_native_roots();
-        let channel: Channel = Channel::from_static("https://youtube.googleapis.com")
-            .tls_config(tls)?
-            .connect()
-            .await?;
-        debug!("gRPC channel connected");
+        debug!(
+            "banning channel_id={} duration_secs={:?}",
+            channel_id, duration_secs
+        );
+        let mut url = self.api_url("liveChat/bans")?;
+        url.query_pairs_mut().append_pair("part", "snippet");
+
+        let mut snippet = serde_json::json!({
+            "liveChatId": live_chat_id,
+            "type": if duration_secs.is_some() { "temporary" } else { "permanent" },
+            "bannedUserDetails": { "channelId": channel_id },
+        });
+        if let Some(secs) = duration_secs {
+            snippet["banDurationSeconds"] = secs.into();
+        }
+        let req = self
+            .http
+            .post(url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(serde_json::json!({ "snippet": snippet }).to_string());
 
-        let mut client = V3DataLiveChatMessageServiceClient::new(channel);
+        self.send_yt_req(req).await?;
+        Ok(())
+    }
+}
 
-        let mut next_page_token: Option<String> = None;
+impl YoutubeService {
+    /// Streams chat until YouTube reports the chat as ended. Transport errors are
+    /// returned to the caller, `cursor` keeps enough state to resume afterwards.
+    pub async fn stream_chat(
+        &self,
+        live_chat_id: &str,
+        cursor: &mut StreamCursor,
+        tx: StreamSender,
+        archive: Option<&ArchiveSink>,
+        recorder: Option<&RecordSink>,
+    ) -> anyhow::Result<()> {
+        debug!("listen start live_chat_id={}", live_chat_id);
+        let mut connected = false;
         let mut poll_cycle: usize = 0;
-        let mut avatar_cache: HashMap<String, KittyAvatar> = HashMap::new();
+        let mut pages = PageState::default();
 
         loop {
             poll_cycle += 1;
             debug!(
                 "stream poll cycle={} page_token_present={}",
                 poll_cycle,
-                next_page_token.is_some()
+                cursor.next_page_token.is_some()
             );
             let req = LiveChatMessageListRequest {
                 part: vec![
@@ -269,104 +374,43 @@ impl YoutubeService {
                     "authorDetails".to_string(),
                 ],
                 live_chat_id: Some(live_chat_id.to_string()),
-                max_results: Some(20),
-                page_token: next_page_token.clone(),
+                max_results: Some(self.chat_config.max_results),
+                page_token: cursor.next_page_token.clone(),
                 profile_image_size: Some(0),
-                hl: Some("en".to_string()),
+                hl: Some(self.chat_config.hl.clone()),
             };
 
-            let mut request = Request::new(req);
-            let auth: MetadataValue<_> = format!("Bearer {}", self.token).parse()?;
-            request.metadata_mut().insert("authorization", auth);
-
-            let mut stream = client.stream_list(request).await?.into_inner();
+            let mut stream = self.chat.stream_list(req).await?;
             let mut got_page = false;
+            if !connected {
+                connected = true;
+                tx.send(StreamEvent::Status(StatusEvent::Connected)).await?;
+            }
 
             while let Some(resp) = stream.message().await? {
                 got_page = true;
-                debug!(
-                    "stream page items={} next_page_token_present={}",
-                    resp.items.len(),
-                    resp.next_page_token.is_some()
-                );
-                for item in resp.items.iter() {
-                    use crate::youtube_api_v3::live_chat_message_snippet::type_wrapper::Type as MessageType;
-
-                    let Some(snippet) = item.snippet.as_ref() else {
-                        debug!("skipping item without snippet");
-                        continue;
-                    };
-
-                    match snippet.r#type() {
-                        MessageType::TextMessageEvent => {
-                            // todo: get these properly
-                            let message = snippet
-                                .display_message
-                                .as_deref()
-                                .unwrap_or("<empty>")
-                                .to_string();
-                            let author = item
-                                .author_details
-                                .as_ref()
-                                .and_then(|d| d.display_name.as_ref())
-                                .map(String::as_str)
-                                .unwrap_or("<unknown>")
-                                .to_string();
-                            let timestamp = snippet
-                                .published_at
-                                .as_deref()
-                                .unwrap()
-                                .get(11..16)
-                                .unwrap_or("--:--")
-                                .to_string();
-                            let is_member = item
-                                .author_details
-                                .as_ref()
-                                .and_then(|d| d.is_chat_sponsor.to_owned())
-                                .unwrap_or(false);
-                            let avatar = if let Some(url) = item
-                                .author_details
-                                .as_ref()
-                                .and_then(|d| d.profile_image_url.as_deref())
-                            {
-                                if let Some(cached) = avatar_cache.get_mut(url) {
-                                    let avatar = Arc::new(cached.clone());
-                                    Some(avatar)
-                                } else if let Some(fetched) = self.fetch_avatar(url).await {
-                                    let avatar = Arc::new(fetched.clone());
-                                    avatar_cache.insert(url.to_string(), fetched);
-                                    Some(avatar)
-                                } else {
-                                    None
-                                }
-                            } else {
-                                None
-                            };
-
-                            tx.send(AppEvent::Chat(ChatMessage {
-                                author,
-                                message,
-                                kind: MessageKind::Text,
-                                timestamp,
-                                avatar,
-                                is_member,
-                            }))
-                            .await?;
-                        }
-                        MessageType::NewSponsorEvent => {}
-                        _ => {}
-                    }
+                if let Some(recorder) = recorder {
+                    recorder.record(&resp);
+                }
+                process_page(Some(self), &resp, cursor, &mut pages, &tx, archive).await?;
+                if pages.chat_ended {
+                    break;
                 }
-
-                next_page_token = resp.next_page_token.clone();
             }
 
             if !got_page {
                 debug!("stream produced no pages in this cycle");
             }
 
-            if next_page_token.is_none() {
-                debug!("next_page_token absent, exiting listen loop");
+            if pages.chat_ended || cursor.next_page_token.is_none() {
+                debug!(
+                    "chat ended={} ended_at={:?}, exiting listen loop",
+                    pages.chat_ended, pages.ended_at
+                );
+                tx.send(StreamEvent::Status(StatusEvent::Disconnected(
+                    DisconnectReason::StreamEnded { at: pages.ended_at },
+                )))
+                .await?;
                 break;
             }
         }
@@ -375,3 +419,316 @@ impl YoutubeService {
         Ok(())
     }
 }
+
+/// What `process_page` carries from one page to the next within a connection.
+#[derive(Default)]
+pub struct PageState {
+    /// Level names only come with membership events, kept per channel id for later messages.
+    member_levels: HashMap<String, String>,
+    pub chat_ended: bool,
+    pub ended_at: Option<DateTime<Utc>>,
+}
+
+/// Turns one page of the chat stream into events, shared by live streaming and
+/// replays. Avatars are only looked up when `yt` is given, messages whose avatar
+/// isn't cached yet go out without it and get it from `AppEvent::AvatarReady`.
+pub async fn process_page(
+    yt: Option<&YoutubeService>,
+    resp: &LiveChatMessageListResponse,
+    cursor: &mut StreamCursor,
+    pages: &mut PageState,
+    tx: &StreamSender,
+    archive: Option<&ArchiveSink>,
+) -> anyhow::Result<()> {
+    debug!(
+        "stream page items={} next_page_token_present={}",
+        resp.items.len(),
+        resp.next_page_token.is_some()
+    );
+    for item in resp.items.iter() {
+        let Some(snippet) = item.snippet.as_ref() else {
+            debug!("skipping item without snippet");
+            continue;
+        };
+        if let Some(id) = item.id.as_deref()
+            && !cursor.mark_seen(id)
+        {
+            debug!("skipping duplicate item id={}", id);
+            continue;
+        }
+        if let Some(archive) = archive {
+            archive.store(item);
+        }
+        if snippet.r#type() == MessageType::ChatEndedEvent {
+            pages.chat_ended = true;
+            pages.ended_at = Some(published_at(snippet));
+            continue;
+        }
+        if let Some(event) = moderation_event(snippet) {
+            tx.send(StreamEvent::Moderation(event)).await?;
+            continue;
+        }
+        if let Some(poll) = poll_from_item(item) {
+            tx.send(StreamEvent::Poll(poll)).await?;
+            continue;
+        }
+        let Some(mut msg) = chat_message(item, tx.stream()) else {
+            debug!("skipping unsupported item type={:?}", snippet.r#type());
+            continue;
+        };
+        match &msg.member_level {
+            Some(level) => {
+                pages
+                    .member_levels
+                    .insert(msg.author_channel_id.clone(), level.clone());
+            }
+            None if msg.is_member => {
+                msg.member_level = pages.member_levels.get(&msg.author_channel_id).cloned();
+            }
+            None => {}
+        }
+
+        let url = item
+            .author_details
+            .as_ref()
+            .and_then(|d| d.profile_image_url.as_deref());
+        msg.avatar = match (yt, url) {
+            (Some(yt), Some(url)) => yt
+                .avatars
+                .as_ref()
+                .and_then(|avatars| avatars.get(url, &msg.author_channel_id)),
+            _ => None,
+        };
+
+        tx.send(StreamEvent::Chat(Box::new(msg))).await?;
+    }
+
+    if let Some(poll) = resp.active_poll_item.as_ref().and_then(poll_from_item) {
+        tx.send(StreamEvent::Poll(poll)).await?;
+    }
+
+    cursor.next_page_token = resp.next_page_token.clone();
+
+    if let Some(offline_at) = resp.offline_at.as_deref() {
+        pages.chat_ended = true;
+        if pages.ended_at.is_none() {
+            pages.ended_at = parse_time(offline_at);
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_time(iso: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(iso)
+        .ok()
+        .map(|at| at.with_timezone(&Utc))
+}
+
+/// When the item was published, the time it arrived if YouTube left it out.
+fn published_at(snippet: &LiveChatMessageSnippet) -> DateTime<Utc> {
+    snippet
+        .published_at
+        .as_deref()
+        .and_then(parse_time)
+        .unwrap_or_else(|| {
+            debug!(
+                "item without a valid published_at: {:?}",
+                snippet.published_at
+            );
+            Utc::now()
+        })
+}
+
+/// Converts a chat item into a message without its avatar, `None` for items that
+/// aren't shown as messages.
+pub fn chat_message(item: &LiveChatMessage, stream: StreamId) -> Option<ChatMessage> {
+    let snippet = item.snippet.as_ref()?;
+    let (kind, message) = message_kind(snippet)?;
+    let author_details = item.author_details.as_ref();
+    let member_level = kind.member_level().map(str::to_string);
+
+    Some(ChatMessage {
+        id: item.id.clone().unwrap_or_default(),
+        stream,
+        timestamp: published_at(snippet),
+        author: author_details
+            .and_then(|d| d.display_name.clone())
+            .unwrap_or_else(|| "<unknown>".to_string()),
+        author_channel_id: author_details
+            .and_then(|d| d.channel_id.clone())
+            .unwrap_or_default(),
+        message,
+        kind,
+        avatar: None,
+        is_member: author_details
+            .and_then(|d| d.is_chat_sponsor)
+            .unwrap_or(false),
+        is_moderator: author_details
+            .and_then(|d| d.is_chat_moderator)
+            .unwrap_or(false),
+        is_owner: author_details
+            .and_then(|d| d.is_chat_owner)
+            .unwrap_or(false),
+        is_verified: author_details.and_then(|d| d.is_verified).unwrap_or(false),
+        member_level,
+        removed: None,
+        delivery: None,
+    })
+}
+
+fn moderation_event(snippet: &LiveChatMessageSnippet) -> Option<ModerationEvent> {
+    match snippet.displayed_content.as_ref()? {
+        DisplayedContent::MessageDeletedDetails(details) => Some(ModerationEvent::MessageDeleted {
+            message_id: details.deleted_message_id.clone()?,
+        }),
+        DisplayedContent::MessageRetractedDetails(details) => {
+            Some(ModerationEvent::MessageRetracted {
+                message_id: details.retracted_message_id.clone()?,
+            })
+        }
+        DisplayedContent::UserBannedDetails(details) => {
+            let user = details.banned_user_details.as_ref()?;
+            let duration_secs = match details.ban_type() {
+                BanType::Temporary => details.ban_duration_seconds,
+                BanType::Permanent => None,
+            };
+            Some(ModerationEvent::UserBanned {
+                channel_id: user.channel_id.clone()?,
+                display_name: user.display_name.clone().unwrap_or_default(),
+                duration_secs,
+                timestamp: published_at(snippet),
+            })
+        }
+        _ => None,
+    }
+}
+
+fn poll_from_item(item: &LiveChatMessage) -> Option<Poll> {
+    let Some(DisplayedContent::PollDetails(details)) = item
+        .snippet
+        .as_ref()
+        .and_then(|s| s.displayed_content.as_ref())
+    else {
+        return None;
+    };
+    let metadata = details.metadata.as_ref()?;
+    let status = match details.status() {
+        ProtoPollStatus::Closed => PollStatus::Closed,
+        ProtoPollStatus::Active | ProtoPollStatus::Unknown => PollStatus::Active,
+    };
+
+    Some(Poll {
+        id: item.id.clone().unwrap_or_default(),
+        question: metadata.question_text.clone().unwrap_or_default(),
+        options: metadata
+            .options
+            .iter()
+            .map(|o| PollOption {
+                text: o.option_text.clone().unwrap_or_default(),
+                tally: o.tally.unwrap_or(0),
+            })
+            .collect(),
+        status,
+    })
+}
+
+fn message_kind(snippet: &LiveChatMessageSnippet) -> Option<(MessageKind, String)> {
+    match (snippet.r#type(), snippet.displayed_content.as_ref()) {
+        (MessageType::TextMessageEvent, _) => {
+            let message = snippet
+                .display_message
+                .as_deref()
+                .unwrap_or("<empty>")
+                .to_string();
+            Some((MessageKind::Text, message))
+        }
+        (MessageType::SuperChatEvent, Some(DisplayedContent::SuperChatDetails(details))) => {
+            let kind = MessageKind::SuperChat {
+                amount: details.amount_display_string.clone().unwrap_or_default(),
+                tier: details.tier.unwrap_or(0),
+            };
+            Some((kind, details.user_comment.clone().unwrap_or_default()))
+        }
+        (MessageType::SuperStickerEvent, Some(DisplayedContent::SuperStickerDetails(details))) => {
+            let sticker = details
+                .super_sticker_metadata
+                .as_ref()
+                .and_then(|m| m.alt_text.clone())
+                .unwrap_or_else(|| "Super Sticker".to_string());
+            let kind = MessageKind::SuperSticker {
+                amount: details.amount_display_string.clone().unwrap_or_default(),
+                tier: details.tier.unwrap_or(0),
+            };
+            Some((kind, sticker))
+        }
+        (MessageType::NewSponsorEvent, Some(DisplayedContent::NewSponsorDetails(details))) => {
+            let kind = MessageKind::Subscription {
+                level: details.member_level_name.clone().unwrap_or_default(),
+                is_upgrade: details.is_upgrade.unwrap_or(false),
+            };
+            Some((kind, String::new()))
+        }
+        (
+            MessageType::MemberMilestoneChatEvent,
+            Some(DisplayedContent::MemberMilestoneChatDetails(details)),
+        ) => {
+            let kind = MessageKind::MemberMilestone {
+                level: details.member_level_name.clone().unwrap_or_default(),
+                months: details.member_month.unwrap_or(0),
+            };
+            Some((kind, details.user_comment.clone().unwrap_or_default()))
+        }
+        (
+            MessageType::MembershipGiftingEvent,
+            Some(DisplayedContent::MembershipGiftingDetails(details)),
+        ) => {
+            let kind = MessageKind::MembershipGifting {
+                count: details.gift_memberships_count.unwrap_or(0),
+                level: details
+                    .gift_memberships_level_name
+                    .clone()
+                    .unwrap_or_default(),
+            };
+            Some((kind, String::new()))
+        }
+        (
+            MessageType::GiftMembershipReceivedEvent,
+            Some(DisplayedContent::GiftMembershipReceivedDetails(details)),
+        ) => {
+            let kind = MessageKind::GiftMembershipReceived {
+                level: details.member_level_name.clone().unwrap_or_default(),
+                gifting_message_id: details
+                    .associated_membership_gifting_message_id
+                    .clone()
+                    .unwrap_or_default(),
+                gifter: None,
+            };
+            Some((kind, String::new()))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seen_ids_forget_the_oldest_first() {
+        let mut cursor = StreamCursor::default();
+        for i in 0..SEEN_IDS_CAPACITY {
+            assert!(cursor.mark_seen(&i.to_string()));
+        }
+        assert!(!cursor.mark_seen("0"));
+
+        // one more pushes out the oldest id, a repeat doesn't count as newer
+        assert!(cursor.mark_seen("new"));
+        assert!(cursor.mark_seen("0"));
+        assert!(!cursor.mark_seen("2"));
+        assert!(cursor.mark_seen("1"));
+        assert_eq!(cursor.seen_ids.len(), SEEN_IDS_CAPACITY);
+        assert_eq!(cursor.seen_order.len(), SEEN_IDS_CAPACITY);
+        assert_eq!(cursor.seen_order.front().map(String::as_str), Some("3"));
+    }
+}
@@ -1,27 +1,91 @@
+use std::future::Future;
 use std::path::PathBuf;
+use std::pin::Pin;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use anyhow::Context;
 use dialoguer::Input;
 use log::debug;
+use yup_oauth2::authenticator::DefaultAuthenticator;
 use yup_oauth2::{InstalledFlowAuthenticator, InstalledFlowReturnMethod};
 
 const APP_DIR: &str = ".youtube-chat-rs";
 const CLIENT_SECRET_FILE: &str = "client_secret.json";
 const TOKEN_CACHE_FILE: &str = "token_cache.json";
+const READONLY_SCOPE: &str = "https://www.googleapis.com/auth/youtube.readonly";
+/// Needed for posting and moderating, covers everything the readonly scope does.
+const FORCE_SSL_SCOPE: &str = "https://www.googleapis.com/auth/youtube.force-ssl";
+/// Tokens closer than this to expiry are refreshed before being handed out.
+const REFRESH_MARGIN_SECS: i64 = 5 * 60;
 
-fn resolve_paths() -> anyhow::Result<(PathBuf, PathBuf)> {
+pub type TokenFuture<'a> = Pin<Box<dyn Future<Output = anyhow::Result<String>> + Send + 'a>>;
+
+/// Source of access tokens, asked before every API call so long sessions
+/// outlive a single token.
+pub trait TokenProvider: Send + Sync {
+    fn token(&self) -> TokenFuture<'_>;
+}
+
+pub struct OAuthTokenProvider {
+    authenticator: DefaultAuthenticator,
+    scopes: Vec<&'static str>,
+}
+
+impl TokenProvider for OAuthTokenProvider {
+    fn token(&self) -> TokenFuture<'_> {
+        Box::pin(async move {
+            let mut access_token = self.authenticator.token(&self.scopes).await?;
+
+            if let Some(expires_at) = access_token.expiration_time() {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs() as i64)
+                    .unwrap_or(0);
+                if expires_at.unix_timestamp() - now < REFRESH_MARGIN_SECS {
+                    debug!("OAuth token expires soon, refreshing");
+                    access_token = self
+                        .authenticator
+                        .force_refreshed_token(&self.scopes)
+                        .await?;
+                }
+            }
+
+            access_token
+                .token()
+                .map(str::to_string)
+                .context("Couldn't get oauth token")
+        })
+    }
+}
+
+/// Stands in for OAuth when nothing needs signing in, e.g. a plaintext mock endpoint.
+pub struct NoTokens;
+
+impl TokenProvider for NoTokens {
+    fn token(&self) -> TokenFuture<'_> {
+        Box::pin(async { anyhow::bail!("Not signed in, this request needs OAuth") })
+    }
+}
+
+/// `~/.youtube-chat-rs`, created if missing.
+pub fn app_dir() -> anyhow::Result<PathBuf> {
     let home = std::env::var("HOME").context("HOME is not set")?;
     let base = PathBuf::from(home).join(APP_DIR);
     std::fs::create_dir_all(&base)
         .with_context(|| format!("Failed to create directory {}", base.display()))?;
 
+    Ok(base)
+}
+
+fn resolve_paths() -> anyhow::Result<(PathBuf, PathBuf)> {
+    let base = app_dir()?;
     let client_secret_path = base.join(CLIENT_SECRET_FILE);
     let token_cache_path = base.join(TOKEN_CACHE_FILE);
 
     Ok((client_secret_path, token_cache_path))
 }
 
-pub async fn auth() -> anyhow::Result<String> {
+pub async fn auth(write: bool) -> anyhow::Result<OAuthTokenProvider> {
     rustls::crypto::ring::default_provider()
         .install_default()
         .unwrap();
@@ -49,16 +113,20 @@ pub async fn auth() -> anyhow::Result<String> {
         .build()
         .await?;
 
-    let scopes = &["https://www.googleapis.com/auth/youtube.readonly"];
-    debug!("requesting OAuth token for readonly scope");
-
-    let access_token = auth.token(scopes).await?;
-
-    let token = match access_token.token() {
-        Some(t) => t,
-        None => panic!("Couldn't get oauth token"),
+    let scope = if write {
+        FORCE_SSL_SCOPE
+    } else {
+        READONLY_SCOPE
     };
+    let provider = OAuthTokenProvider {
+        authenticator: auth,
+        scopes: vec![scope],
+    };
+    debug!("requesting OAuth token for scope {}", scope);
+
+    // run the consent flow up front, before the TUI takes over the terminal
+    provider.token().await?;
 
     debug!("OAuth token acquired");
-    Ok(token.to_string())
+    Ok(provider)
 }
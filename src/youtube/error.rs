@@ -0,0 +1,167 @@
+use crate::app::event::{AppError, ErrorCategory};
+use reqwest::StatusCode;
+use std::fmt;
+use tonic::Code;
+
+/// Non-success response from the YouTube Data API.
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub body: String,
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "YouTube API error ({}): {}", self.status, self.body)
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+pub fn classify(err: &anyhow::Error) -> ErrorCategory {
+    for cause in err.chain() {
+        if let Some(api) = cause.downcast_ref::<ApiError>() {
+            return classify_api_error(api);
+        }
+        if let Some(status) = cause.downcast_ref::<tonic::Status>() {
+            return classify_grpc_status(status);
+        }
+        if cause.is::<yup_oauth2::Error>() {
+            return ErrorCategory::AuthExpired;
+        }
+        if cause.is::<tonic::transport::Error>() || cause.is::<reqwest::Error>() {
+            return ErrorCategory::Network;
+        }
+    }
+
+    ErrorCategory::Other
+}
+
+fn classify_api_error(err: &ApiError) -> ErrorCategory {
+    if err.body.contains("quotaExceeded") || err.body.contains("rateLimitExceeded") {
+        return ErrorCategory::QuotaExceeded;
+    }
+
+    match err.status {
+        StatusCode::UNAUTHORIZED => ErrorCategory::AuthExpired,
+        StatusCode::NOT_FOUND => ErrorCategory::ChatNotFound,
+        s if s.is_server_error() => ErrorCategory::Network,
+        _ if err.body.contains("liveChatNotFound") => ErrorCategory::ChatNotFound,
+        _ => ErrorCategory::Other,
+    }
+}
+
+fn classify_grpc_status(status: &tonic::Status) -> ErrorCategory {
+    match status.code() {
+        Code::ResourceExhausted => ErrorCategory::QuotaExceeded,
+        Code::PermissionDenied if status.message().contains("quota") => {
+            ErrorCategory::QuotaExceeded
+        }
+        Code::Unauthenticated => ErrorCategory::AuthExpired,
+        Code::NotFound => ErrorCategory::ChatNotFound,
+        Code::Unavailable | Code::DeadlineExceeded | Code::Aborted | Code::Unknown => {
+            ErrorCategory::Network
+        }
+        _ => ErrorCategory::Other,
+    }
+}
+
+/// Wraps a task failure into the event sent to the UI.
+pub fn app_error(source: &str, err: &anyhow::Error) -> AppError {
+    AppError {
+        category: classify(err),
+        message: format!("{}: {}", source, err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    fn api(status: u16, body: &str) -> anyhow::Error {
+        ApiError {
+            status: StatusCode::from_u16(status).unwrap(),
+            body: body.to_string(),
+        }
+        .into()
+    }
+
+    fn grpc(code: Code, message: &str) -> anyhow::Error {
+        tonic::Status::new(code, message).into()
+    }
+
+    #[test]
+    fn classifies_api_errors() {
+        let cases = [
+            (
+                api(403, r#"{"reason": "quotaExceeded"}"#),
+                ErrorCategory::QuotaExceeded,
+            ),
+            (
+                api(403, r#"{"reason": "rateLimitExceeded"}"#),
+                ErrorCategory::QuotaExceeded,
+            ),
+            (api(401, "Invalid Credentials"), ErrorCategory::AuthExpired),
+            (api(404, ""), ErrorCategory::ChatNotFound),
+            (
+                api(403, r#"{"reason": "liveChatNotFound"}"#),
+                ErrorCategory::ChatNotFound,
+            ),
+            (api(503, "Backend Error"), ErrorCategory::Network),
+            (api(400, "Bad Request"), ErrorCategory::Other),
+        ];
+        for (err, category) in cases {
+            assert_eq!(classify(&err), category, "{}", err);
+        }
+    }
+
+    #[test]
+    fn classifies_grpc_statuses() {
+        let cases = [
+            (
+                grpc(Code::ResourceExhausted, ""),
+                ErrorCategory::QuotaExceeded,
+            ),
+            (
+                grpc(Code::PermissionDenied, "quota exceeded"),
+                ErrorCategory::QuotaExceeded,
+            ),
+            (
+                grpc(Code::PermissionDenied, "not a moderator"),
+                ErrorCategory::Other,
+            ),
+            (grpc(Code::Unauthenticated, ""), ErrorCategory::AuthExpired),
+            (grpc(Code::NotFound, ""), ErrorCategory::ChatNotFound),
+            (grpc(Code::Unavailable, ""), ErrorCategory::Network),
+            (grpc(Code::DeadlineExceeded, ""), ErrorCategory::Network),
+            (grpc(Code::InvalidArgument, ""), ErrorCategory::Other),
+        ];
+        for (err, category) in cases {
+            assert_eq!(classify(&err), category, "{}", err);
+        }
+    }
+
+    #[test]
+    fn classifies_other_errors() {
+        let request = reqwest::Client::new().get("not a url").build().unwrap_err();
+        let cases = [
+            (
+                yup_oauth2::Error::MissingAccessToken.into(),
+                ErrorCategory::AuthExpired,
+            ),
+            (request.into(), ErrorCategory::Network),
+            (anyhow::anyhow!("something else"), ErrorCategory::Other),
+            // the cause is found behind added context
+            (
+                Err::<(), _>(grpc(Code::NotFound, ""))
+                    .context("chat stream")
+                    .unwrap_err(),
+                ErrorCategory::ChatNotFound,
+            ),
+        ];
+        for (err, category) in cases {
+            assert_eq!(classify(&err), category, "{:#}", err);
+        }
+    }
+}
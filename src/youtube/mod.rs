@@ -1,24 +1,269 @@
+use crate::app::event::{
+    Command, DisconnectReason, ErrorCategory, OutgoingResult, StatusEvent, StreamEvent,
+    StreamSender,
+};
+use crate::archive::ArchiveSink;
+use crate::replay::RecordSink;
+use crate::youtube::api::{StreamCursor, YoutubeService};
+use crate::youtube::error::app_error;
+use log::debug;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
 use tokio::sync::mpsc;
-use crate::app::event::AppEvent;
-use crate::youtube::api::YoutubeService;
 
 pub mod api;
 pub mod auth;
+pub mod error;
 pub mod models;
+pub mod source;
+
+const RECONNECT_BASE_DELAY: Duration = Duration::from_secs(1);
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(60);
 
 pub fn spawn_youtube_chat_task(
     yt: YoutubeService,
     live_chat_id: String,
-    tx: mpsc::Sender<AppEvent>
+    tx: StreamSender,
+    archive: Option<ArchiveSink>,
+    recorder: Option<RecordSink>,
 ) -> tokio::task::JoinHandle<()> {
     tokio::spawn(async move {
-        // todo let _ = tx.send(AppEvent::Status(StatusEvent::Connecting))
-        
-        match yt.stream_chat(&live_chat_id, tx.clone()).await {
-            Ok(_) => {},
-            Err(e) => {
-                // todo: AppEvent::Error
+        let mut cursor = StreamCursor::default();
+        let mut backoff = Backoff::default();
+
+        while !tx.is_closed() {
+            let _ = tx.send(StreamEvent::Status(StatusEvent::Connecting)).await;
+            let resume_token = cursor.next_page_token.clone();
+
+            match yt
+                .stream_chat(
+                    &live_chat_id,
+                    &mut cursor,
+                    tx.clone(),
+                    archive.as_ref(),
+                    recorder.as_ref(),
+                )
+                .await
+            {
+                Ok(_) => break,
+                Err(e) => {
+                    debug!("chat stream failed: {:#}", e);
+                    let error = app_error("chat stream", &e);
+                    let category = error.category;
+                    let _ = tx.send(StreamEvent::Error(error)).await;
+                    let reason = if category == ErrorCategory::ChatNotFound {
+                        DisconnectReason::ChatGone
+                    } else {
+                        DisconnectReason::Error(e.to_string())
+                    };
+                    let _ = tx
+                        .send(StreamEvent::Status(StatusEvent::Disconnected(reason)))
+                        .await;
+                    if category == ErrorCategory::ChatNotFound {
+                        debug!("live chat is gone, giving up on reconnecting");
+                        break;
+                    }
+                }
             }
+
+            // any progress since the last attempt means the connection was healthy
+            let delay = backoff.next_delay(cursor.next_page_token != resume_token);
+            debug!("reconnecting in {:?} attempt={}", delay, backoff.attempt);
+            tokio::time::sleep(delay).await;
         }
     })
-}
\ No newline at end of file
+}
+
+pub fn spawn_command_task(
+    yt: YoutubeService,
+    live_chat_id: String,
+    mut commands: mpsc::Receiver<Command>,
+    tx: StreamSender,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        while let Some(command) = commands.recv().await {
+            let event = match command {
+                Command::SendMessage { local_id, text } => {
+                    let result = match yt.insert_message(&live_chat_id, &text).await {
+                        Ok(message_id) => OutgoingResult::Sent {
+                            local_id,
+                            message_id,
+                        },
+                        Err(e) => {
+                            debug!("sending chat message failed: {:#}", e);
+                            OutgoingResult::Failed {
+                                local_id,
+                                error: app_error("send message", &e),
+                            }
+                        }
+                    };
+                    Some(StreamEvent::Outgoing(result))
+                }
+                // the chat stream reports successful moderation, only failures need an event
+                Command::DeleteMessage { message_id } => yt
+                    .delete_message(&message_id)
+                    .await
+                    .err()
+                    .map(|e| StreamEvent::Error(app_error("delete message", &e))),
+                Command::BanUser {
+                    channel_id,
+                    duration_secs,
+                } => yt
+                    .ban_user(&live_chat_id, &channel_id, duration_secs)
+                    .await
+                    .err()
+                    .map(|e| StreamEvent::Error(app_error("ban user", &e))),
+            };
+
+            if let Some(event) = event
+                && tx.send(event).await.is_err()
+            {
+                break;
+            }
+        }
+    })
+}
+
+/// Reconnect attempts since the chat stream last made progress.
+#[derive(Default)]
+struct Backoff {
+    attempt: u32,
+}
+
+impl Backoff {
+    /// The delay before the next attempt, starting over if the last one made progress.
+    fn next_delay(&mut self, progressed: bool) -> Duration {
+        if progressed {
+            self.attempt = 0;
+        }
+        let delay = reconnect_delay(self.attempt);
+        self.attempt = self.attempt.saturating_add(1);
+        delay
+    }
+}
+
+/// Exponential backoff with jitter, somewhere between half and the full delay.
+fn reconnect_delay(attempt: u32) -> Duration {
+    let max = RECONNECT_BASE_DELAY
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(RECONNECT_MAX_DELAY);
+    let jitter = RandomState::new().build_hasher().finish() % 1000;
+    max / 2 + max / 2 * jitter as u32 / 1000
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::event::AppEvent;
+    use crate::config::ChatConfig;
+    use crate::youtube::api::{DEFAULT_API_URL, Endpoints};
+    use crate::youtube::auth::NoTokens;
+    use crate::youtube_api_v3::v3_data_live_chat_message_service_server::{
+        V3DataLiveChatMessageService, V3DataLiveChatMessageServiceServer,
+    };
+    use crate::youtube_api_v3::{LiveChatMessageListRequest, LiveChatMessageListResponse};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio_stream::wrappers::ReceiverStream;
+    use tonic::transport::server::TcpIncoming;
+    use tonic::{Request, Response, Status};
+
+    fn full_delay(attempt: u32) -> Duration {
+        RECONNECT_BASE_DELAY
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(RECONNECT_MAX_DELAY)
+    }
+
+    #[test]
+    fn reconnect_delay_stays_between_half_and_full_backoff() {
+        for attempt in (0..40).chain([u32::MAX]) {
+            let max = full_delay(attempt);
+            for _ in 0..20 {
+                let delay = reconnect_delay(attempt);
+                assert!(
+                    delay >= max / 2 && delay <= max,
+                    "attempt={} delay={:?}",
+                    attempt,
+                    delay
+                );
+            }
+        }
+        assert_eq!(full_delay(0), Duration::from_secs(1));
+        assert_eq!(full_delay(u32::MAX), RECONNECT_MAX_DELAY);
+    }
+
+    #[test]
+    fn backoff_starts_over_after_progress() {
+        let mut backoff = Backoff::default();
+        assert!(backoff.next_delay(false) <= Duration::from_secs(1));
+        for _ in 0..10 {
+            backoff.next_delay(false);
+        }
+        assert!(backoff.next_delay(false) >= RECONNECT_MAX_DELAY / 2);
+
+        assert!(backoff.next_delay(true) <= Duration::from_secs(1));
+        assert_eq!(backoff.attempt, 1);
+    }
+
+    /// Answers every stream request like YouTube does for a chat that no longer exists.
+    struct GoneChat {
+        requests: Arc<AtomicUsize>,
+    }
+
+    #[tonic::async_trait]
+    impl V3DataLiveChatMessageService for GoneChat {
+        type StreamListStream = ReceiverStream<Result<LiveChatMessageListResponse, Status>>;
+
+        async fn stream_list(
+            &self,
+            _request: Request<LiveChatMessageListRequest>,
+        ) -> Result<Response<Self::StreamListStream>, Status> {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            Err(Status::not_found("live chat not found"))
+        }
+    }
+
+    #[tokio::test]
+    async fn chat_task_gives_up_when_the_chat_is_gone() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let incoming = TcpIncoming::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = incoming.local_addr().unwrap();
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(V3DataLiveChatMessageServiceServer::new(GoneChat {
+                    requests: requests.clone(),
+                }))
+                .serve_with_incoming(incoming),
+        );
+
+        let endpoints = Endpoints {
+            api: DEFAULT_API_URL.to_string(),
+            grpc: format!("http://{}", addr),
+        };
+        let yt = YoutubeService::new(Arc::new(NoTokens), endpoints, ChatConfig::default(), None)
+            .unwrap();
+        let (tx, mut rx) = mpsc::channel(16);
+        let task =
+            spawn_youtube_chat_task(yt, "gone".to_string(), StreamSender::new(0, tx), None, None);
+        // no reconnect sleep, the task ends on its own
+        tokio::time::timeout(Duration::from_secs(5), task)
+            .await
+            .expect("chat task kept reconnecting")
+            .unwrap();
+
+        let mut events = Vec::new();
+        while let Some(AppEvent::Stream(_, event)) = rx.recv().await {
+            events.push(event);
+        }
+        assert!(matches!(
+            events.as_slice(),
+            [
+                StreamEvent::Status(StatusEvent::Connecting),
+                StreamEvent::Error(error),
+                StreamEvent::Status(StatusEvent::Disconnected(DisconnectReason::ChatGone)),
+            ] if error.category == ErrorCategory::ChatNotFound
+        ));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+}
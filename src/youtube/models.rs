@@ -18,7 +18,7 @@ pub struct LiveStreamingDetails {
     pub active_live_chat_id: Option<String>,
 
     #[serde(rename = "concurrentViewers")]
-    pub concurrent_viewers: Option<String>
+    pub concurrent_viewers: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -45,3 +45,8 @@ pub struct SearchId {
     #[serde(rename = "channelId")]
     pub channel_id: Option<String>,
 }
+
+#[derive(serde::Deserialize, Debug)]
+pub struct LiveChatMessageResource {
+    pub id: String,
+}
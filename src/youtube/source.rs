@@ -0,0 +1,64 @@
+use crate::youtube::auth::TokenProvider;
+use crate::youtube_api_v3::v3_data_live_chat_message_service_client::V3DataLiveChatMessageServiceClient;
+use crate::youtube_api_v3::{LiveChatMessageListRequest, LiveChatMessageListResponse};
+use log::debug;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use tonic::Request;
+use tonic::codec::Streaming;
+use tonic::metadata::MetadataValue;
+use tonic::transport::{Channel, ClientTlsConfig, Endpoint};
+
+pub type PagesFuture<'a> = Pin<
+    Box<dyn Future<Output = anyhow::Result<Streaming<LiveChatMessageListResponse>>> + Send + 'a>,
+>;
+
+/// Where `stream_chat` gets its pages from, one server stream per call.
+pub trait ChatSource: Send + Sync {
+    fn stream_list(&self, request: LiveChatMessageListRequest) -> PagesFuture<'_>;
+}
+
+/// `V3DataLiveChatMessageService` over gRPC, YouTube itself or anything serving
+/// the same service such as `ytc mock-server`.
+pub struct GrpcChatSource {
+    channel: Channel,
+    /// `None` for plaintext endpoints, tokens are never sent unencrypted.
+    tokens: Option<Arc<dyn TokenProvider>>,
+}
+
+impl GrpcChatSource {
+    pub fn new(url: &str, tokens: Arc<dyn TokenProvider>) -> anyhow::Result<Self> {
+        let endpoint = Endpoint::from_shared(url.to_string())?;
+        let secure = endpoint.uri().scheme_str() == Some("https");
+        let endpoint = if secure {
+            endpoint.tls_config(ClientTlsConfig::new().with_native_roots())?
+        } else {
+            debug!(
+                "using plaintext gRPC endpoint {}, requests are unauthenticated",
+                url
+            );
+            endpoint
+        };
+
+        Ok(Self {
+            channel: endpoint.connect_lazy(),
+            tokens: secure.then_some(tokens),
+        })
+    }
+}
+
+impl ChatSource for GrpcChatSource {
+    fn stream_list(&self, request: LiveChatMessageListRequest) -> PagesFuture<'_> {
+        Box::pin(async move {
+            let mut request = Request::new(request);
+            if let Some(tokens) = &self.tokens {
+                let auth: MetadataValue<_> = format!("Bearer {}", tokens.token().await?).parse()?;
+                request.metadata_mut().insert("authorization", auth);
+            }
+
+            let mut client = V3DataLiveChatMessageServiceClient::new(self.channel.clone());
+            Ok(client.stream_list(request).await?.into_inner())
+        })
+    }
+}